@@ -0,0 +1,51 @@
+//! Observation of the congestion window and the RTTs over the lifetime of the
+//! connection, such that the BDP it reached can be remembered for the next one
+//! without requiring the application to track it.
+
+use pluginop_wasm::{PluginEnv, Duration, quic::RecoveryField};
+
+use crate::{BdpRecord, PLUGIN_DATA};
+
+/// The samples are ignored while the smoothed RTT exceeds the min RTT by this
+/// ratio, the path being congested or changing.
+const MAX_SRTT_RATIO: u32 = 2;
+/// The weight of a new sample in the estimate, as for the smoothed RTT.
+const SAMPLE_WEIGHT: u64 = 8;
+
+/// Take a sample of the recovery state and update the BDP estimate.
+///
+/// The delivery rate is approximated by the congestion window over the
+/// smoothed RTT, such that the estimate (rate times min RTT) does not account
+/// for the bytes sitting in queues. The estimate is an exponentially weighted
+/// moving average of the samples taken while the RTT is stable, so that a
+/// transient spike does not become the resume target.
+pub fn sample(penv: &PluginEnv) {
+    let cwin: usize = penv.get_recovery(RecoveryField::CongestionWindow);
+    let srtt: Duration = penv.get_recovery(RecoveryField::SmoothedRtt);
+    let min_rtt: Duration = penv.get_recovery(RecoveryField::MinRtt);
+    let now = match penv.get_unix_instant() {
+        Ok(n) => n,
+        Err(_) => return,
+    };
+    if srtt.is_zero() || min_rtt.is_zero() || srtt > min_rtt * MAX_SRTT_RATIO {
+        return;
+    }
+    let bdp = (cwin as u128 * min_rtt.as_nanos() / srtt.as_nanos().max(min_rtt.as_nanos())) as u64;
+    let pd = PLUGIN_DATA.get_mut();
+    let record = pd.captured.get_or_insert(BdpRecord { cwin: bdp, min_rtt, saved_at: now.secs(), lifetime: pd.token_lifetime });
+    record.cwin = (((SAMPLE_WEIGHT - 1) as u128 * record.cwin as u128 + bdp as u128) / SAMPLE_WEIGHT as u128) as u64;
+    record.min_rtt = record.min_rtt.min(min_rtt);
+    record.saved_at = now.secs();
+}
+
+#[no_mangle]
+pub extern "C" fn post_process_frame_2(penv: &mut PluginEnv) -> i64 {
+    sample(penv);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn post_process_frame_3(penv: &mut PluginEnv) -> i64 {
+    sample(penv);
+    0
+}
@@ -0,0 +1,114 @@
+//! Careful resume of a remembered BDP. Instead of jumping straight to the
+//! remembered congestion window, the path is first observed during one RTT to
+//! check that it matches the saved min RTT. Then the congestion window is
+//! increased towards the remembered one over several RTTs. Any sign of loss
+//! falls back to the congestion window of before the ramp, and to the normal
+//! congestion control of the host.
+
+use pluginop_wasm::{PluginEnv, Duration, quic::RecoveryField};
+use plugin_support::{IntoCode, Result};
+
+use crate::{BdpRecord, PLUGIN_DATA};
+
+/// The timer (and its identifier) driving the careful resume.
+pub const CR_TIMER: u64 = 0xbd;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ResumeState {
+    /// Normal congestion control of the host.
+    Idle,
+    /// Observing the path for one RTT before trusting the record.
+    Validating { record: BdpRecord },
+    /// Growing the congestion window towards the one of the record.
+    Ramping { record: BdpRecord, from: usize, round: u32, last_cwin: usize },
+}
+
+/// The time to wait before the next step of the careful resume.
+fn next_round(penv: &PluginEnv, record: &BdpRecord) -> Duration {
+    let srtt: Duration = penv.get_recovery(RecoveryField::SmoothedRtt);
+    if srtt.is_zero() { record.min_rtt } else { srtt }
+}
+
+fn arm_timer(penv: &mut PluginEnv, record: &BdpRecord) -> Result<()> {
+    let next = penv.get_unix_instant()? + next_round(penv, record);
+    Ok(penv.set_timer(next, CR_TIMER, CR_TIMER)?)
+}
+
+/// Start the careful resume of `record`.
+pub fn start(penv: &mut PluginEnv, record: BdpRecord) -> Result<()> {
+    PLUGIN_DATA.get_mut().resume = ResumeState::Validating { record };
+    penv.print(&format!("Validating BDP record with cwin {} and min RTT {:?}", record.cwin, record.min_rtt));
+    arm_timer(penv, &record)
+}
+
+/// Stop the careful resume and let the host congestion control take over.
+/// The congestion window is brought back to its value before the ramp, which
+/// was not validated. The slow start threshold is only set once the ramp
+/// completes, so it needs no restoring.
+pub fn fall_back(penv: &mut PluginEnv, reason: &str) {
+    let resume = std::mem::replace(&mut PLUGIN_DATA.get_mut().resume, ResumeState::Idle);
+    match resume {
+        ResumeState::Idle => return,
+        // Nothing changed yet.
+        ResumeState::Validating { .. } => {},
+        ResumeState::Ramping { from, .. } => {
+            let cwin: usize = penv.get_recovery(RecoveryField::CongestionWindow);
+            if cwin > from && penv.set_recovery(RecoveryField::CongestionWindow, from).is_err() {
+                penv.print("Cannot restore the congestion window");
+            }
+        },
+    }
+    let _ = penv.cancel_timer(CR_TIMER);
+    penv.print(&format!("Careful resume aborted: {}", reason));
+}
+
+fn to_usize(v: u64) -> usize {
+    usize::try_from(v).unwrap_or(usize::MAX)
+}
+
+/// Set the congestion window to the value of the given ramp round, unless the
+/// host already grew it beyond.
+fn ramp(penv: &mut PluginEnv, record: BdpRecord, from: usize, round: u32, current: usize) -> Result<()> {
+    let rounds = PLUGIN_DATA.ramp_rtts;
+    // In 64 bits, usize being 32-bit on wasm32.
+    let step = record.cwin.saturating_sub(from as u64) as u128 * round.min(rounds) as u128 / rounds as u128;
+    let cwin = to_usize(from as u64 + step as u64).max(current);
+    penv.set_recovery(RecoveryField::CongestionWindow, cwin)?;
+    if round >= rounds {
+        penv.set_recovery(RecoveryField::Ssthresh, to_usize(record.cwin))?;
+        PLUGIN_DATA.get_mut().resume = ResumeState::Idle;
+        penv.print(&format!("Successfully set CWIN to {}", cwin));
+        return Ok(());
+    }
+    PLUGIN_DATA.get_mut().resume = ResumeState::Ramping { record, from, round, last_cwin: cwin };
+    arm_timer(penv, &record)
+}
+
+#[no_mangle]
+pub extern "C" fn on_plugin_timeout_bd(penv: &mut PluginEnv) -> i64 {
+    let cwin: usize = penv.get_recovery(RecoveryField::CongestionWindow);
+    let res = match PLUGIN_DATA.resume {
+        ResumeState::Idle => Ok(()),
+        ResumeState::Validating { record } => {
+            let min_rtt: Duration = penv.get_recovery(RecoveryField::MinRtt);
+            if !record.matches_path(min_rtt) {
+                fall_back(penv, &format!("path min RTT {:?} does not match saved {:?}", min_rtt, record.min_rtt));
+                return 0;
+            }
+            if cwin as u64 >= record.cwin {
+                PLUGIN_DATA.get_mut().resume = ResumeState::Idle;
+                return 0;
+            }
+            ramp(penv, record, cwin, 1, cwin)
+        },
+        ResumeState::Ramping { record, from, round, last_cwin } => {
+            // The host reduced the window, most likely because of a loss.
+            if cwin < last_cwin {
+                fall_back(penv, &format!("congestion window reduced to {}", cwin));
+                return 0;
+            }
+            ramp(penv, record, from, round + 1, cwin)
+        },
+    };
+    res.into_code()
+}
@@ -1,203 +1,426 @@
 use std::format;
 
-use pluginop_wasm::{PluginEnv, PluginCell, Bytes, quic::{QVal, Registration, Frame, ExtensionFrame, FrameSendKind, FrameSendOrder, FrameRegistration, PacketType, RecoveryField}};
+use pluginop_wasm::{Bytes, PluginEnv, PluginCell, PluginVal, Duration, UnixInstant, quic::{Registration, FrameSendKind, FrameSendOrder, FrameRegistration, PacketType, RecoveryField, ConnectionField}};
+use plugin_support::{extension_frame, frame::ShouldSend, frame_ops::FrameOps, input, plugin_manifest, poctl::{self, ids}, save_all, tp, varint, Error, IntoCode, Result};
 use lazy_static::lazy_static;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+mod capture;
+mod careful_resume;
+
+use careful_resume::ResumeState;
+
+/// The BDP state remembered from a previous connection, as carried by the
+/// BDP frame.
+#[derive(Clone, Copy, Debug)]
+struct BdpRecord {
+    /// The congestion window, in bytes.
+    cwin: u64,
+    /// The minimum RTT observed on the path.
+    min_rtt: Duration,
+    /// When the record was saved, in seconds since the UNIX epoch.
+    saved_at: u64,
+    /// For how long after `saved_at` the record can be used.
+    lifetime: Duration,
+}
+
+impl BdpRecord {
+    /// The number of bytes the record needs on the wire.
+    fn wire_len(&self) -> usize {
+        varint::len(self.cwin)
+            + varint::len(self.min_rtt.as_micros() as u64)
+            + varint::len(self.saved_at)
+            + varint::len(self.lifetime.as_secs())
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) -> varint::Result<()> {
+        varint::write(self.cwin, buf)?;
+        varint::write(self.min_rtt.as_micros() as u64, buf)?;
+        varint::write(self.saved_at, buf)?;
+        varint::write(self.lifetime.as_secs(), buf)
+    }
+
+    fn read(penv: &mut PluginEnv, tag: u64) -> varint::Result<Self> {
+        Ok(BdpRecord {
+            cwin: varint::read(penv, tag)?,
+            min_rtt: Duration::from_micros(varint::read(penv, tag)?),
+            saved_at: varint::read(penv, tag)?,
+            lifetime: Duration::from_secs(varint::read(penv, tag)?),
+        })
+    }
+
+    /// Whether the record is still valid at `now`.
+    fn is_fresh(&self, now: UnixInstant) -> bool {
+        now.secs() >= self.saved_at && now.secs() - self.saved_at <= self.lifetime.as_secs()
+    }
+
+    /// Whether the record was likely measured on the same path as the one
+    /// having `min_rtt` as minimum RTT. An unknown (zero) RTT matches any path.
+    fn matches_path(&self, min_rtt: Duration) -> bool {
+        if self.min_rtt.is_zero() || min_rtt.is_zero() {
+            return true;
+        }
+        min_rtt <= self.min_rtt * MAX_RTT_RATIO && self.min_rtt <= min_rtt * MAX_RTT_RATIO
+    }
+}
+
+/// A BDP record along with the tag authenticating it. The tag is computed by
+/// the server issuing the record and is empty when no secret is in use.
+#[derive(Clone, Debug)]
+struct BdpToken {
+    record: BdpRecord,
+    tag: Vec<u8>,
+}
+
+impl BdpToken {
+    fn wire_len(&self) -> usize {
+        self.record.wire_len() + varint::len(self.tag.len() as u64) + self.tag.len()
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) -> varint::Result<()> {
+        self.record.write(buf)?;
+        varint::write(self.tag.len() as u64, buf)?;
+        buf.extend_from_slice(&self.tag);
+        Ok(())
+    }
+
+    /// Read a token, a tag longer than the issued ones being a framing error.
+    fn read(penv: &mut PluginEnv, tag: u64) -> Result<Self> {
+        let record = BdpRecord::read(penv, tag)?;
+        let tag_len = varint::read(penv, tag)?;
+        if tag_len > TAG_LEN as u64 {
+            return Err(Error::FrameEncoding);
+        }
+        let token_tag = if tag_len > 0 { plugin_support::get_exact(penv, tag, tag_len)? } else { Vec::new() };
+        Ok(BdpToken { record, tag: token_tag })
+    }
+
+    /// Issue a token for `record`, authenticated with `secret`.
+    fn sign(record: BdpRecord, secret: &[u8]) -> varint::Result<Self> {
+        let tag = new_mac(secret, &record)?.finalize().into_bytes()[..TAG_LEN].to_vec();
+        Ok(BdpToken { record, tag })
+    }
+
+    /// Whether the token was issued with `secret`.
+    fn verify(&self, secret: &[u8]) -> bool {
+        match new_mac(secret, &self.record) {
+            Ok(mac) => self.tag.len() == TAG_LEN && mac.verify_truncated_left(&self.tag).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Convert the token as plugin values, as expected by `plugin_control_80000`.
+    fn to_plugin_vals(&self) -> Vec<PluginVal> {
+        let mut vals = vec![
+            self.record.cwin.into(),
+            self.record.min_rtt.into(),
+            UnixInstant::at(self.record.saved_at, 0).into(),
+            self.record.lifetime.into(),
+        ];
+        vals.extend(poctl::bytes_to_words(&self.tag));
+        vals
+    }
+}
+
+fn new_mac(secret: &[u8], record: &BdpRecord) -> varint::Result<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    let mut buf = Vec::with_capacity(record.wire_len());
+    record.write(&mut buf)?;
+    mac.update(&buf);
+    Ok(mac)
+}
 
 #[derive(Debug)]
 struct PluginData {
     should_send: bool,
-    previous_cwin: u64,
-    cwin_to_set: u64,
+    /// The token to send to the peer.
+    previous: Option<BdpToken>,
+    /// The token received from the peer, waiting to be processed.
+    to_apply: Option<BdpToken>,
+    /// On the client, the last token issued by the server.
+    received: Option<BdpToken>,
+    /// On the server, the secret used to authenticate the issued tokens.
+    secret: Option<Vec<u8>>,
+    /// On the server, the lifetime of the issued tokens.
+    token_lifetime: Duration,
+    /// On the server, the progress of the careful resume of a received record.
+    resume: ResumeState,
+    /// The number of RTTs over which the congestion window is increased.
+    ramp_rtts: u32,
+    /// The BDP estimate of the current connection.
+    captured: Option<BdpRecord>,
+    /// On the server, whether the token of the closing connection was issued.
+    issued_at_close: bool,
 }
 
 const BD_FRAME_TYPE: u64 = 0xBD;
+const BF_TP_TYPE: u64 = 0xBF;
+
+plugin_manifest! {
+    name: "bdp-frame",
+    frames: [BD_FRAME_TYPE],
+    transport_parameters: [BF_TP_TYPE],
+    timers: [careful_resume::CR_TIMER],
+    plugin_controls: [ids::BDP_SET_TOKEN, ids::BDP_CONFIGURE_SERVER, ids::BDP_GET_TOKEN, ids::BDP_SET_RAMP_RTTS, ids::BDP_GET_CAPTURED],
+    calls: [],
+}
+
+/// The length of the (truncated) HMAC-SHA256 tag authenticating tokens.
+const TAG_LEN: usize = 16;
+
+/// How much the min RTT of the current path may differ from the saved one
+/// before the record is considered to come from another path.
+const MAX_RTT_RATIO: u32 = 2;
+
+/// The largest number of RTTs over which the congestion window can be ramped.
+const MAX_RAMP_RTTS: u64 = 64;
 
 lazy_static! {
     static ref PLUGIN_DATA: PluginCell<PluginData> = PluginCell::new(PluginData {
         should_send: false,
-        previous_cwin: 3000000,
-        cwin_to_set: 0,
+        previous: None,
+        to_apply: None,
+        received: None,
+        secret: None,
+        token_lifetime: Duration::from_secs(3600),
+        resume: ResumeState::Idle,
+        ramp_rtts: 4,
+        captured: None,
+        issued_at_close: false,
     });
 }
 
 // Initialize the plugin.
 #[no_mangle]
-pub extern fn init(penv: &mut PluginEnv) -> i64 {
-    match penv.register(Registration::TransportParameter(0xBF)) {
-        Ok(()) => (),
-        _ => return -2,
-    };
-    match penv.register(Registration::Frame(FrameRegistration::new(BD_FRAME_TYPE, FrameSendOrder::First, FrameSendKind::OncePerPacket, true, true))) {
-        Ok(()) => 0,
-        _ => -1,
-    }
+pub extern "C" fn init(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        penv.register(Registration::TransportParameter(BF_TP_TYPE)).map_err(|_| Error::Registration)?;
+        penv.register(Registration::Frame(FrameRegistration::new(BD_FRAME_TYPE, FrameSendOrder::First, FrameSendKind::OncePerPacket, true, true))).map_err(|_| Error::Registration)
+    })();
+    res.into_code()
 }
 
 #[no_mangle]
-pub extern fn decode_transport_parameter_bf(penv: &mut PluginEnv) -> i64 {
+pub extern "C" fn decode_transport_parameter_bf(penv: &mut PluginEnv) -> i64 {
     // This is a zero-length TP. We just got it.
-    if let Ok(false) = penv.get_connection(pluginop_wasm::quic::ConnectionField::IsServer) {
-        PLUGIN_DATA.get_mut().should_send = true;
+    if let Ok(false) = penv.get_connection(ConnectionField::IsServer) {
+        PLUGIN_DATA.get_mut().should_send = PLUGIN_DATA.previous.is_some();
     }
     penv.enable();
     0
 }
 
 #[no_mangle]
-pub extern fn write_transport_parameter_bf(penv: &mut PluginEnv) -> i64 {
-    let bytes = match penv.get_input::<Bytes>(0) {
-        Ok(b) => b,
-        _ => return -1,
-    };
-    // 3 bytes because this is a varint.
-    let tp_bytes: [u8; 3] = [0x40, 0xbf, 0x00];
-    match penv.put_bytes(bytes.tag, &tp_bytes) {
-        Ok(3) => {},
-        _ => return -4,
-    };
-    0
+pub extern "C" fn write_transport_parameter_bf(penv: &mut PluginEnv) -> i64 {
+    tp::write(penv, BF_TP_TYPE, &[]).into_code()
 }
 
-// This function determines if there are plugin frames that must be
-// sent now or not.
-#[no_mangle]
-pub extern fn should_send_frame_bd(penv: &mut PluginEnv) -> i64 {
-    let pkt_type = match penv.get_input::<QVal>(0) {
-        Ok(QVal::PacketType(pt)) => pt,
-        _ => return -1,
-    };
-    let is_closing = match penv.get_input::<bool>(2) {
-        Ok(b) => b,
-        _ => return -2,
-    };
-    let out = pkt_type == PacketType::Short && !is_closing && PLUGIN_DATA.should_send;
-    match penv.save_output(out.into()) {
-        Ok(()) => 0,
-        Err(_) => -3,
+struct BdpFrame;
+
+impl FrameOps for BdpFrame {
+    const FRAME_TYPE: u64 = BD_FRAME_TYPE;
+
+    // This function determines if there are plugin frames that must be
+    // sent now or not.
+    fn should_send(penv: &mut PluginEnv, ss: ShouldSend) -> Result<bool> {
+        let is_server: bool = penv.get_connection(ConnectionField::IsServer)?;
+        if ss.is_closing && is_server && PLUGIN_DATA.secret.is_some() && !PLUGIN_DATA.issued_at_close {
+            // Last chance to observe the connection, hand its estimate to the
+            // client for the next one.
+            PLUGIN_DATA.get_mut().issued_at_close = true;
+            issue_token(penv)?;
+        }
+        Ok(ss.packet_type == PacketType::Short && (is_server || !ss.is_closing) && PLUGIN_DATA.should_send && PLUGIN_DATA.previous.is_some())
     }
-}
 
-#[no_mangle]
-pub extern fn prepare_frame_bd(penv: &mut PluginEnv) -> i64 {
-    // We need to save the extension frame.
-    match penv.save_output(Frame::Extension(ExtensionFrame { frame_type: BD_FRAME_TYPE, tag: 0 }).into()) {
-        Ok(()) => 0,
-        _ => -1,
+    fn prepare(_penv: &mut PluginEnv) -> Result<u64> {
+        // At most one frame at a time, no need for a specific tag.
+        Ok(0)
     }
-}
 
-#[no_mangle]
-pub extern fn write_frame_bd(penv: &mut PluginEnv) -> i64 {
-    // No need to get the extension frame, we know what it should contain.
-    let bytes = match penv.get_input::<Bytes>(1) {
-        Ok(b) => b,
-        _ => return -3,
-    };
-    let mut frame_bytes = [0u8; 10];
-    frame_bytes[0..2].copy_from_slice(&[0x40, 0xbd]);
-    frame_bytes[2..10].copy_from_slice(&PLUGIN_DATA.previous_cwin.to_be_bytes());
-    match penv.put_bytes(bytes.tag, &frame_bytes) {
-        Ok(10) => {},
-        _ => return -4,
-    };
-    match penv.save_output(frame_bytes.len().into()) {
-        Ok(()) => 0,
-        _ => -5,
+    fn wire_len(_penv: &mut PluginEnv, _tag: u64) -> Result<usize> {
+        let token = PLUGIN_DATA.previous.as_ref().ok_or(Error::InvalidState)?;
+        Ok(token.wire_len())
     }
-}
 
-#[no_mangle]
-pub extern fn log_frame_bd(penv: &mut PluginEnv) -> i64 {
-    // We know the content it should have.
-    let bytes = match penv.get_input::<Bytes>(1) {
-        Ok(b) => b,
-        _ => return -2,
-    };
-    let s = format!("BDP_FRAME with cwin value {}", PLUGIN_DATA.previous_cwin);
-    let s_bytes = s.into_bytes();
-    let s_len = s_bytes.len();
-    match penv.put_bytes(bytes.tag, &s_bytes) {
-        Ok(l) if l == s_len => 0,
-        _ => -3,
+    fn write(_penv: &mut PluginEnv, _tag: u64) -> Result<Vec<u8>> {
+        // We know what the frame should contain.
+        let token = PLUGIN_DATA.previous.as_ref().ok_or(Error::InvalidState)?;
+        let mut content = Vec::with_capacity(token.wire_len());
+        token.write(&mut content)?;
+        Ok(content)
     }
-}
 
-#[no_mangle]
-pub extern fn parse_frame_bd(penv: &mut PluginEnv) -> i64 {
-    let bytes = match penv.get_input::<Bytes>(0) {
-        Ok(b) => b,
-        _ => return -1,
-    };
+    fn log(_penv: &mut PluginEnv, _tag: u64) -> Result<String> {
+        // We know the content it should have.
+        Ok(match &PLUGIN_DATA.previous {
+            Some(BdpToken { record: r, tag }) => format!("BDP_FRAME with cwin {}, min RTT {:?}, saved at {}, lifetime {:?} and {}-byte tag", r.cwin, r.min_rtt, r.saved_at, r.lifetime, tag.len()),
+            None => "Invalid BDP_FRAME".to_string(),
+        })
+    }
 
-    // Get the data, only one byte is actually needed to parse the val
-    // (as the type frame is already parsed).
-    let val = match penv.get_bytes(bytes.tag, 8) {
-        Ok(v) => u64::from_be_bytes(v.try_into().expect("wut")),
-        _ => return -2,
-    };
-    PLUGIN_DATA.get_mut().cwin_to_set = val;
+    fn parse(penv: &mut PluginEnv, bytes: Bytes) -> Result<u64> {
+        // The frame type is already parsed, only the token remains.
+        let token = BdpToken::read(penv, bytes.tag)?;
+        PLUGIN_DATA.get_mut().to_apply = Some(token);
+        Ok(0)
+    }
 
-    match penv.save_output(Frame::Extension(ExtensionFrame { frame_type: BD_FRAME_TYPE, tag: 0 }).into()) {
-        Ok(()) => 0,
-        _ => -3,
+    fn process(penv: &mut PluginEnv, _tag: u64) -> Result<()> {
+        let token = PLUGIN_DATA.get_mut().to_apply.take().ok_or(Error::InvalidState)?;
+        let is_server: bool = penv.get_connection(ConnectionField::IsServer)?;
+        if !is_server {
+            // The server issued this token for our next connection, let the
+            // application retrieve it.
+            PLUGIN_DATA.get_mut().received = Some(token);
+            return Ok(());
+        }
+        let record = token.record;
+        let Some(secret) = &PLUGIN_DATA.secret else {
+            // Anyone could inflate our congestion window otherwise.
+            penv.print("Ignoring BDP record, no secret is configured to authenticate it");
+            return Ok(());
+        };
+        if !token.verify(secret) {
+            penv.print("Ignoring BDP record with invalid tag");
+            return Ok(());
+        }
+        let now = penv.get_unix_instant()?;
+        if !record.is_fresh(now) {
+            penv.print(&format!("Ignoring stale BDP record saved at {}", record.saved_at));
+            return Ok(());
+        }
+        let min_rtt: Duration = penv.get_recovery(RecoveryField::MinRtt);
+        if !record.matches_path(min_rtt) {
+            penv.print(&format!("Ignoring BDP record with min RTT {:?} while path has {:?}", record.min_rtt, min_rtt));
+            return Ok(());
+        }
+        careful_resume::start(penv, record)
     }
-}
 
-#[no_mangle]
-pub extern fn process_frame_bd(penv: &mut PluginEnv) -> i64 {
-    // We know what to do here.
-    if let Err(_) = penv.set_recovery(RecoveryField::CongestionWindow, PLUGIN_DATA.cwin_to_set as usize) {
-        return -1;
+    fn on_frame_reserved(penv: &mut PluginEnv, _tag: u64) -> Result<()> {
+        PLUGIN_DATA.get_mut().should_send = false;
+        penv.print("BDP frame sent");
+        Ok(())
     }
-    if let Err(_) = penv.set_recovery(RecoveryField::Ssthresh, PLUGIN_DATA.cwin_to_set as usize) {
-        return -2;
+
+    fn notify(penv: &mut PluginEnv, _tag: u64, is_lost: bool) -> Result<()> {
+        penv.print(&format!("BDP was lost? {}", is_lost));
+        // Only resend it, the loss of our own frame says nothing about the
+        // path that the careful resume uses.
+        PLUGIN_DATA.get_mut().should_send = is_lost;
+        Ok(())
     }
-    penv.print(&format!("Successfully set CWIN to {}", PLUGIN_DATA.cwin_to_set));
-    0
 }
 
+// Export all the "*_frame_bd" protocol operations.
+extension_frame!(BdpFrame, "bd");
+
+/// On the client, provide the token to send to the server, i.e., the values
+/// returned by `plugin_control_80003` during a previous connection. On the
+/// server, issue a token with the current BDP state and send it to the client.
+/// A server with a secret also issues one when the connection closes.
 #[no_mangle]
-pub extern fn wire_len_bd(penv: &mut PluginEnv) -> i64 {
-    // The frame always have the same size.
-    let len: usize = 2 + 8; // Just the frame type and eight byte of data for now.
-                            // And 0xbd needs 2 bytes...
-    match penv.save_output(len.into()) {
-        Ok(()) => 0,
-        _ => -1,
-    }
+pub extern "C" fn plugin_control_80000(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let is_server: bool = penv.get_connection(ConnectionField::IsServer)?;
+        if is_server {
+            return issue_token(penv);
+        }
+        let cwin: u64 = input(penv, 0)?;
+        let min_rtt: Duration = input(penv, 1)?;
+        let saved_at: UnixInstant = input(penv, 2)?;
+        let lifetime: Duration = input(penv, 3)?;
+        // The (optional) tag follows, as 64-bit words.
+        let inputs = penv.get_inputs()?;
+        let tag = poctl::words_to_bytes(&inputs, 4).ok_or(Error::BadInput(4))?;
+        let record = BdpRecord { cwin, min_rtt, saved_at: saved_at.secs(), lifetime };
+        PLUGIN_DATA.get_mut().previous = Some(BdpToken { record, tag });
+        PLUGIN_DATA.get_mut().should_send = true;
+        Ok(())
+    })();
+    res.into_code()
+}
+
+fn issue_token(penv: &mut PluginEnv) -> Result<()> {
+    let secret = PLUGIN_DATA.secret.as_ref().ok_or(Error::InvalidState)?;
+    let now = penv.get_unix_instant()?;
+    // Prefer the estimate over the whole connection to the current state.
+    capture::sample(penv);
+    let record = match PLUGIN_DATA.captured {
+        Some(r) => r,
+        None => {
+            let cwin: usize = penv.get_recovery(RecoveryField::CongestionWindow);
+            let min_rtt: Duration = penv.get_recovery(RecoveryField::MinRtt);
+            BdpRecord { cwin: cwin as u64, min_rtt, saved_at: now.secs(), lifetime: PLUGIN_DATA.token_lifetime }
+        },
+    };
+    PLUGIN_DATA.get_mut().previous = Some(BdpToken::sign(record, secret)?);
+    PLUGIN_DATA.get_mut().should_send = true;
+    Ok(())
 }
 
+/// On the server, configure the lifetime of the issued tokens (input 0) and
+/// the secret authenticating them, as 64-bit words (the following inputs).
+/// Received tokens are only accepted once a secret is set, and if they were
+/// issued with it.
 #[no_mangle]
-pub extern fn on_frame_reserved_bd(penv: &mut PluginEnv) -> i64 {
-    PLUGIN_DATA.get_mut().should_send = false;
-    penv.print("BDP frame sent");
-    0
+pub extern "C" fn plugin_control_80002(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let lifetime: Duration = input(penv, 0)?;
+        let inputs = penv.get_inputs()?;
+        let secret = match poctl::words_to_bytes(&inputs, 1) {
+            Some(s) if !s.is_empty() => s,
+            _ => return Err(Error::BadInput(1)),
+        };
+        PLUGIN_DATA.get_mut().token_lifetime = lifetime;
+        PLUGIN_DATA.get_mut().secret = Some(secret);
+        Ok(())
+    })();
+    res.into_code()
 }
 
+/// Set the number of RTTs (input 0, at most [`MAX_RAMP_RTTS`]) over which the
+/// congestion window grows towards the remembered one.
 #[no_mangle]
-pub extern fn notify_frame_bd(penv: &mut PluginEnv) -> i64 {
-    // is_lost is input 1
-    let is_lost = match penv.get_input::<bool>(1) {
-        Ok(l) => l,
-        _ => return -1,
-    };
-    penv.print(&format!("BDP was lost? {}", is_lost));
-    PLUGIN_DATA.get_mut().should_send = is_lost;
-    0
+pub extern "C" fn plugin_control_80004(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        match input::<u64>(penv, 0)? {
+            n if n > 0 && n <= MAX_RAMP_RTTS => PLUGIN_DATA.get_mut().ramp_rtts = n as u32,
+            _ => return Err(Error::BadInput(0)),
+        }
+        Ok(())
+    })();
+    res.into_code()
 }
 
+/// On the client, return the last token issued by the server, in the format
+/// expected by `plugin_control_80000`. Returns nothing if no token was received.
 #[no_mangle]
-pub extern fn plugin_control_80000(penv: &mut PluginEnv) -> i64 {
-    match penv.get_connection::<bool>(pluginop_wasm::quic::ConnectionField::IsServer) {
-        Ok(false) => {},
-        Ok(true) => return 0,
-        _ => return -1,
-    }
-    let new_cwin = match penv.get_input::<u64>(0) {
-        Ok(n) => n,
-        _ => return -2,
+pub extern "C" fn plugin_control_80003(penv: &mut PluginEnv) -> i64 {
+    let vals = match &PLUGIN_DATA.received {
+        Some(t) => t.to_plugin_vals(),
+        None => vec![],
     };
-    PLUGIN_DATA.get_mut().previous_cwin = new_cwin;
-    PLUGIN_DATA.get_mut().should_send = true;
-    0
-}
\ No newline at end of file
+    save_all(penv, &vals).into_code()
+}
+
+/// Return the BDP estimated over the current connection, in the format
+/// expected by `plugin_control_80000`. Returns nothing if no RTT sample was
+/// available yet. The record is signed when a secret is set, such that a
+/// server can hand it out as a token. Otherwise it is untagged and only meant
+/// to be persisted by the application, as servers reject such tokens.
+#[no_mangle]
+pub extern "C" fn plugin_control_80005(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        capture::sample(penv);
+        let vals = match (PLUGIN_DATA.captured, &PLUGIN_DATA.secret) {
+            (Some(record), Some(secret)) => BdpToken::sign(record, secret)?.to_plugin_vals(),
+            (Some(record), None) => BdpToken { record, tag: vec![] }.to_plugin_vals(),
+            (None, _) => vec![],
+        };
+        save_all(penv, &vals)
+    })();
+    res.into_code()
+}
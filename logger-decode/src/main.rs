@@ -0,0 +1,84 @@
+//! Convert the binary traces of the `logger` plugin, written to stdout.
+//!
+//! - `logger-decode <trace.blog>`: as a qlog 0.3 JSON-SEQ trace, the same as
+//!   the plugin would have written.
+//! - `logger-decode --csv <trace.blog>`: as CSV, one `time,name,field,value`
+//!   line per field of the events, the nested fields of the qlog event data
+//!   being joined with dots, e.g., `frames.0.frame_type`.
+
+use std::{io::{self, BufWriter, Write}, process::ExitCode};
+
+use logger_events::{binary::Decoder, qlog, Record};
+use serde_json::Value;
+
+#[derive(Clone, Copy)]
+enum Output {
+    Qlog,
+    Csv,
+}
+
+/// Quote the `field` if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Write a CSV line for each leaf of `data`, named by its `path`.
+fn csv_fields(out: &mut impl Write, record: &Record, path: &str, data: &Value) -> io::Result<()> {
+    let child = |key: &str| if path.is_empty() { key.to_string() } else { format!("{path}.{key}") };
+    match data {
+        Value::Object(map) => map.iter().try_for_each(|(k, v)| csv_fields(out, record, &child(k), v)),
+        Value::Array(values) => values.iter().enumerate().try_for_each(|(i, v)| csv_fields(out, record, &child(&i.to_string()), v)),
+        Value::String(s) => csv_line(out, record, path, s),
+        v => csv_line(out, record, path, &v.to_string()),
+    }
+}
+
+fn csv_line(out: &mut impl Write, record: &Record, field: &str, value: &str) -> io::Result<()> {
+    writeln!(out, "{},{},{},{}", qlog::ms(record.time), qlog::name(&record.event), csv_field(field), csv_field(value))
+}
+
+fn decode(output: Output, path: &str) -> Result<(), String> {
+    let trace = std::fs::read(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+    let (header, records) = Decoder::new(&trace).map_err(|e| format!("{path}: {e}"))?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    let write_err = |e: io::Error| format!("cannot write the output: {e}");
+    match output {
+        Output::Qlog => out.write_all(&qlog::json_seq(&qlog::header(&header))).map_err(write_err)?,
+        Output::Csv => writeln!(out, "time,name,field,value").map_err(write_err)?,
+    }
+    for record in records {
+        // Keep what was decoded before a truncated or malformed record.
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                out.flush().map_err(write_err)?;
+                return Err(format!("{path}: {e}"));
+            },
+        };
+        match output {
+            Output::Qlog => out.write_all(&qlog::json_seq(&qlog::event(&record))).map_err(write_err)?,
+            Output::Csv => csv_fields(&mut out, &record, "", &qlog::data(&record.event)).map_err(write_err)?,
+        }
+    }
+    out.flush().map_err(write_err)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let res = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [path] if !path.starts_with('-') => decode(Output::Qlog, path),
+        ["--csv", path] => decode(Output::Csv, path),
+        _ => Err("usage: logger-decode [--csv] <trace.blog>".to_string()),
+    };
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -0,0 +1,92 @@
+//! The compact binary encoding of a trace. It starts with [`MAGIC`], followed
+//! by the [`Header`] and the [`Record`]s, each of them being prefixed by its
+//! length as a little-endian `u32` and serialized with postcard.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Header, Record};
+
+/// The start of a binary trace, including the version of the encoding.
+pub const MAGIC: &[u8; 8] = b"PQLOGBN1";
+
+#[derive(Debug)]
+pub enum Error {
+    /// The trace does not start with [`MAGIC`].
+    BadMagic,
+    /// The trace ends in the middle of a record.
+    Truncated,
+    /// A record cannot be deserialized.
+    Malformed(postcard::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::BadMagic => write!(f, "not a binary trace"),
+            Error::Truncated => write!(f, "truncated record"),
+            Error::Malformed(e) => write!(f, "malformed record: {e}"),
+        }
+    }
+}
+
+fn encode<T: Serialize>(v: &T, out: &mut Vec<u8>) {
+    let bytes = postcard::to_allocvec(v).expect("serializable value");
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(&bytes);
+}
+
+/// Encode the start of a trace, i.e., [`MAGIC`] and the `header`.
+pub fn header(header: &Header) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    encode(header, &mut out);
+    out
+}
+
+/// Encode a `record` of the trace.
+pub fn record(record: &Record) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode(record, &mut out);
+    out
+}
+
+/// Decoder of a binary trace, iterating over its records.
+pub struct Decoder<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// Decode the start of the `trace`.
+    pub fn new(trace: &'a [u8]) -> Result<(Header, Self), Error> {
+        let rest = trace.strip_prefix(MAGIC.as_slice()).ok_or(Error::BadMagic)?;
+        let mut decoder = Decoder { rest };
+        let header = decoder.next_value()?.ok_or(Error::Truncated)?;
+        Ok((header, decoder))
+    }
+
+    fn next_value<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        let (len, rest) = self.rest.split_first_chunk::<4>().ok_or(Error::Truncated)?;
+        let len = u32::from_le_bytes(*len) as usize;
+        if rest.len() < len {
+            return Err(Error::Truncated);
+        }
+        let v = postcard::from_bytes(&rest[..len]).map_err(Error::Malformed)?;
+        self.rest = &rest[len..];
+        Ok(Some(v))
+    }
+}
+
+impl Iterator for Decoder<'_> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = self.next_value().transpose();
+        if let Some(Err(_)) = res {
+            // Do not loop on the error.
+            self.rest = &[];
+        }
+        res
+    }
+}
@@ -0,0 +1,192 @@
+//! The events logged by the `logger` plugin, shared with the tools decoding
+//! its binary traces.
+//!
+//! A trace starts with a [`Header`], followed by [`Record`]s. These are
+//! serialized as qlog 0.3 events (see [`qlog`]) or with the compact [`binary`]
+//! encoding.
+
+pub use pluginop_common::quic::{Frame, KPacketNumberSpace, PacketType};
+use serde::{Deserialize, Serialize};
+pub use std::time::Duration;
+
+/// The start of the trace.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub is_server: bool,
+    /// The time of the start of the connection since the UNIX epoch, the
+    /// record times being relative to it.
+    pub reference_time: Duration,
+    /// The connection, as known when the trace started.
+    pub connection: Connection,
+}
+
+/// A connection ID of an endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionId {
+    pub sequence_number: u64,
+    pub id: Vec<u8>,
+}
+
+/// The transport parameters exposed by the host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransportParameters {
+    pub ack_delay_exponent: Option<u64>,
+}
+
+/// What identifies the connection, the fields unknown to the host being
+/// empty. The peer transport parameters are only known once the handshake
+/// completes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Connection {
+    /// The negotiated QUIC version.
+    pub version: Option<u32>,
+    /// The connection IDs of the endpoint, used by the peer to reach it.
+    pub local_cids: Vec<ConnectionId>,
+    /// The connection IDs of the peer.
+    pub peer_cids: Vec<ConnectionId>,
+    /// The negotiated application protocol.
+    pub alpn: Option<Vec<u8>>,
+    /// The sent transport parameters.
+    pub local_parameters: TransportParameters,
+    /// The received transport parameters.
+    pub peer_parameters: TransportParameters,
+}
+
+impl Connection {
+    /// The first connection ID of the client, i.e., the one of its first
+    /// Initial packet, which both endpoints know under sequence number 0.
+    /// It identifies the connection in the traces of the client and the
+    /// server.
+    pub fn client_cid(&self, is_server: bool) -> Option<&[u8]> {
+        let cids = if is_server { &self.peer_cids } else { &self.local_cids };
+        cids.iter().find(|c| c.sequence_number == 0).map(|c| c.id.as_slice())
+    }
+}
+
+/// What happened to a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrameEvent {
+    /// The frame was received and processed.
+    Processed,
+    Sent,
+    Acked,
+    Lost,
+}
+
+/// Whether a packet was sent or received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PacketEvent {
+    Sent,
+    Received,
+}
+
+/// A sent or received packet. The logger does not record them, the host of
+/// `pluginop` 0.1 having no protocol operation per packet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Packet {
+    pub packet_type: PacketType,
+    /// The packet number space, see [`epoch`].
+    pub epoch: Option<KPacketNumberSpace>,
+    pub packet_number: Option<u64>,
+    /// The size of the packet on the wire, in bytes.
+    pub size: u64,
+    /// The types of the frames it holds, see [`frame_type`], the unknown ones
+    /// being omitted.
+    pub frames: Vec<u64>,
+}
+
+/// The packet number space of the `packet_type`, if it has packet numbers.
+pub fn epoch(packet_type: PacketType) -> Option<KPacketNumberSpace> {
+    match packet_type {
+        PacketType::Initial => Some(KPacketNumberSpace::Initial),
+        PacketType::Handshake => Some(KPacketNumberSpace::Handshake),
+        PacketType::ZeroRTT | PacketType::Short => Some(KPacketNumberSpace::ApplicationData),
+        PacketType::Retry | PacketType::VersionNegotiation => None,
+    }
+}
+
+/// The type of `f` on the wire, if known to this crate.
+pub fn frame_type(f: &Frame) -> Option<u64> {
+    let ty = match f {
+        Frame::Padding(_) => 0x00,
+        Frame::Ping(_) => 0x01,
+        Frame::ACK(a) => 0x02 | a.ecn_counts.is_some() as u64,
+        Frame::ResetStream(_) => 0x04,
+        Frame::StopSending(_) => 0x05,
+        Frame::Crypto(_) => 0x06,
+        Frame::NewToken(_) => 0x07,
+        Frame::Stream(s) => 0x08 | (s.offset.is_some() as u64) << 2 | (s.length.is_some() as u64) << 1 | s.fin as u64,
+        Frame::MaxData(_) => 0x10,
+        Frame::MaxStreamData(_) => 0x11,
+        Frame::MaxStreams(m) => 0x12 | m.unidirectional as u64,
+        Frame::DataBlocked(_) => 0x14,
+        Frame::StreamDataBlocked(_) => 0x15,
+        Frame::StreamsBlocked(s) => 0x16 | s.unidirectional as u64,
+        Frame::NewConnectionId(_) => 0x18,
+        Frame::RetireConnectionId(_) => 0x19,
+        Frame::PathChallenge(_) => 0x1a,
+        Frame::PathResponse(_) => 0x1b,
+        // Only the transport variant carries the type of the offending frame.
+        Frame::ConnectionClose(c) => if c.frame_type.is_some() { 0x1c } else { 0x1d },
+        Frame::HandshakeDone(_) => 0x1e,
+        Frame::Extension(e) => e.frame_type,
+        _ => return None,
+    };
+    Some(ty)
+}
+
+/// The recovery metrics that changed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricsUpdate {
+    pub min_rtt: Option<Duration>,
+    pub smoothed_rtt: Option<Duration>,
+    pub latest_rtt: Option<Duration>,
+    pub rtt_variance: Option<Duration>,
+    pub congestion_window: Option<u64>,
+    pub bytes_in_flight: Option<u64>,
+    pub ssthresh: Option<u64>,
+    /// The flow control limit of the peer, not part of qlog.
+    pub max_tx_data: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    Frame {
+        kind: FrameEvent,
+        frame: Frame,
+        /// The exponent encoding the ACK delay of ACK frames.
+        ack_delay_exponent: u8,
+        /// The `(smallest, largest)` packet numbers acknowledged by ACK
+        /// frames, from the largest ones. The frame only holds the bytes of
+        /// the ranges after the first one.
+        acked_ranges: Vec<(u64, u64)>,
+    },
+    MetricsUpdated(MetricsUpdate),
+    Packet {
+        kind: PacketEvent,
+        packet: Packet,
+    },
+    /// The connection is known to be different than in the header, e.g.,
+    /// once the handshake completes.
+    ConnectionUpdated(Connection),
+    /// A protocol operation got an input of an unexpected type, and failed.
+    UnexpectedInput {
+        operation: String,
+        index: u32,
+        /// The type of the input, or `missing`.
+        found: String,
+    },
+    /// Events were dropped since the last report, e.g., because the output
+    /// reached its size limit.
+    EventsDropped { count: u64 },
+}
+
+/// An event occurring `time` after the reference time of the trace.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub time: Duration,
+    pub event: Event,
+}
+
+pub mod binary;
+pub mod qlog;
@@ -0,0 +1,288 @@
+//! The representation of the trace as qlog 0.3, serialized as JSON-SEQ
+//! records such that it can be loaded in qvis and the other qlog tools.
+
+use serde_json::{json, Map, Value};
+
+use crate::{Connection, ConnectionId, Duration, Event, Frame, FrameEvent, Header, KPacketNumberSpace, MetricsUpdate, Packet, PacketEvent, PacketType, Record, TransportParameters};
+
+pub const VERSION: &str = "0.3";
+
+/// The record separator starting each JSON-SEQ record.
+const RS: u8 = 0x1e;
+
+/// Serialize `v` as a JSON-SEQ record.
+pub fn json_seq(v: &Value) -> Vec<u8> {
+    let mut out = vec![RS];
+    serde_json::to_writer(&mut out, v).expect("serializable value");
+    out.push(b'\n');
+    out
+}
+
+/// The time in milliseconds, as used by qlog.
+pub fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// The header of the trace. Its `group_id` is the first connection ID of the
+/// client, such that the traces of both endpoints share it.
+pub fn header(header: &Header) -> Value {
+    let mut common_fields = json!({
+        "time_format": "relative",
+        "reference_time": ms(header.reference_time),
+    });
+    if let Some(cid) = header.connection.client_cid(header.is_server) {
+        common_fields["group_id"] = hex(cid).into();
+    }
+    json!({
+        "qlog_version": VERSION,
+        "qlog_format": "JSON-SEQ",
+        "title": "pluginop logger",
+        "trace": {
+            "vantage_point": { "type": role(header.is_server) },
+            "common_fields": common_fields,
+            "connection": connection(&header.connection),
+        },
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The `connection` field of the header, not part of qlog.
+pub fn connection(c: &Connection) -> Value {
+    let cids = |cids: &[ConnectionId]| -> Value {
+        cids.iter().map(|c| json!({ "sequence_number": c.sequence_number, "connection_id": hex(&c.id) })).collect()
+    };
+    let parameters = |p: &TransportParameters| json!({ "ack_delay_exponent": p.ack_delay_exponent });
+    json!({
+        "version": c.version.map(|v| format!("{v:08x}")),
+        "local_cids": cids(&c.local_cids),
+        "peer_cids": cids(&c.peer_cids),
+        "alpn": c.alpn.as_deref().map(String::from_utf8_lossy),
+        "local_parameters": parameters(&c.local_parameters),
+        "peer_parameters": parameters(&c.peer_parameters),
+    })
+}
+
+pub fn role(is_server: bool) -> &'static str {
+    if is_server { "server" } else { "client" }
+}
+
+/// The name of the qlog event. qlog has no event for frames outside of a
+/// packet, sent and acknowledged frames are `pluginop:frames_sent` and
+/// `pluginop:frames_acked` events, lost ones being
+/// `recovery:marked_for_retransmit`.
+pub fn name(event: &Event) -> &'static str {
+    match event {
+        Event::Frame { kind: FrameEvent::Processed, .. } => "transport:frames_processed",
+        Event::Frame { kind: FrameEvent::Sent, .. } => "pluginop:frames_sent",
+        Event::Frame { kind: FrameEvent::Acked, .. } => "pluginop:frames_acked",
+        Event::Frame { kind: FrameEvent::Lost, .. } => "recovery:marked_for_retransmit",
+        Event::MetricsUpdated(_) => "recovery:metrics_updated",
+        Event::Packet { kind: PacketEvent::Sent, .. } => "transport:packet_sent",
+        Event::Packet { kind: PacketEvent::Received, .. } => "transport:packet_received",
+        Event::ConnectionUpdated(_) => "pluginop:connection_updated",
+        Event::UnexpectedInput { .. } => "pluginop:unexpected_input",
+        Event::EventsDropped { .. } => "pluginop:events_dropped",
+    }
+}
+
+/// The data of the qlog event.
+pub fn data(event: &Event) -> Value {
+    match event {
+        Event::Frame { frame: f, ack_delay_exponent, acked_ranges, .. } => json!({ "frames": [frame(f, *ack_delay_exponent, acked_ranges)] }),
+        Event::UnexpectedInput { operation, index, found } => json!({ "operation": operation, "index": index, "found": found }),
+        Event::MetricsUpdated(m) => metrics(m),
+        Event::Packet { packet, .. } => self::packet(packet),
+        Event::ConnectionUpdated(c) => connection(c),
+        Event::EventsDropped { count } => json!({ "count": count }),
+    }
+}
+
+/// The qlog event of the `record`.
+pub fn event(record: &Record) -> Value {
+    json!({ "time": ms(record.time), "name": name(&record.event), "data": data(&record.event) })
+}
+
+fn metrics(m: &MetricsUpdate) -> Value {
+    let mut data = Map::new();
+    let durations = [
+        ("min_rtt", m.min_rtt),
+        ("smoothed_rtt", m.smoothed_rtt),
+        ("latest_rtt", m.latest_rtt),
+        ("rtt_variance", m.rtt_variance),
+    ];
+    for (name, v) in durations {
+        if let Some(v) = v {
+            data.insert(name.to_string(), ms(v).into());
+        }
+    }
+    let counts = [
+        ("congestion_window", m.congestion_window),
+        ("bytes_in_flight", m.bytes_in_flight),
+        ("ssthresh", m.ssthresh),
+        ("max_tx_data", m.max_tx_data),
+    ];
+    for (name, v) in counts {
+        if let Some(v) = v {
+            data.insert(name.to_string(), v.into());
+        }
+    }
+    Value::Object(data)
+}
+
+/// The data of the packet events, the frames only holding their type. The
+/// `epoch` of the header is not part of qlog.
+fn packet(p: &Packet) -> Value {
+    let packet_type = match p.packet_type {
+        PacketType::Initial => "initial",
+        PacketType::Retry => "retry",
+        PacketType::Handshake => "handshake",
+        PacketType::ZeroRTT => "0RTT",
+        PacketType::VersionNegotiation => "version_negotiation",
+        PacketType::Short => "1RTT",
+    };
+    let epoch = p.epoch.map(|e| match e {
+        KPacketNumberSpace::Initial => "initial",
+        KPacketNumberSpace::Handshake => "handshake",
+        KPacketNumberSpace::ApplicationData => "application_data",
+    });
+    let frames: Vec<_> = p.frames.iter().map(|&ty| match frame_type_name(ty) {
+        "unknown" => json!({ "frame_type": "unknown", "raw_frame_type": ty }),
+        name => json!({ "frame_type": name }),
+    }).collect();
+    json!({
+        "header": { "packet_type": packet_type, "packet_number": p.packet_number, "epoch": epoch },
+        "raw": { "length": p.size },
+        "frames": frames,
+    })
+}
+
+/// The qlog name of the frame type `ty`.
+pub fn frame_type_name(ty: u64) -> &'static str {
+    match ty {
+        0x00 => "padding",
+        0x01 => "ping",
+        0x02 | 0x03 => "ack",
+        0x04 => "reset_stream",
+        0x05 => "stop_sending",
+        0x06 => "crypto",
+        0x07 => "new_token",
+        0x08..=0x0f => "stream",
+        0x10 => "max_data",
+        0x11 => "max_stream_data",
+        0x12 | 0x13 => "max_streams",
+        0x14 => "data_blocked",
+        0x15 => "stream_data_blocked",
+        0x16 | 0x17 => "streams_blocked",
+        0x18 => "new_connection_id",
+        0x19 => "retire_connection_id",
+        0x1a => "path_challenge",
+        0x1b => "path_response",
+        0x1c | 0x1d => "connection_close",
+        0x1e => "handshake_done",
+        _ => "unknown",
+    }
+}
+
+/// The qlog representation of `f`. The ACK delay of ACK frames is decoded with
+/// `ack_delay_exponent`, their ranges being `acked_ranges` if known.
+pub fn frame(f: &Frame, ack_delay_exponent: u8, acked_ranges: &[(u64, u64)]) -> Value {
+    match f {
+        Frame::Padding(p) => json!({ "frame_type": "padding", "length": p.length }),
+        Frame::Ping(_) => json!({ "frame_type": "ping" }),
+        Frame::ACK(a) => {
+            // Saturate the delays of malformed traces rather than overflowing.
+            let ack_delay = a.ack_delay.checked_shl(ack_delay_exponent.into()).filter(|v| v >> ack_delay_exponent == a.ack_delay);
+            let ack_delay = Duration::from_micros(ack_delay.unwrap_or(u64::MAX));
+            let smallest = a.largest_acknowledged.saturating_sub(a.first_ack_range);
+            let ranges: Vec<_> = match acked_ranges {
+                [] => vec![[smallest, a.largest_acknowledged]],
+                r => r.iter().map(|&(smallest, largest)| [smallest, largest]).collect(),
+            };
+            let mut v = json!({
+                "frame_type": "ack",
+                "ack_delay": ms(ack_delay),
+                "acked_ranges": ranges,
+            });
+            if let Some(ecn) = &a.ecn_counts {
+                v["ect0"] = ecn.ect0_count.into();
+                v["ect1"] = ecn.ect1_count.into();
+                v["ce"] = ecn.ectce_count.into();
+            }
+            v
+        },
+        Frame::ResetStream(r) => json!({
+            "frame_type": "reset_stream",
+            "stream_id": r.stream_id,
+            "error_code": r.application_protocol_error_code,
+            "final_size": r.final_size,
+        }),
+        Frame::StopSending(s) => json!({
+            "frame_type": "stop_sending",
+            "stream_id": s.stream_id,
+            "error_code": s.application_protocol_error_code,
+        }),
+        Frame::Crypto(c) => json!({ "frame_type": "crypto", "offset": c.offset, "length": c.length }),
+        Frame::NewToken(t) => json!({ "frame_type": "new_token", "token": { "length": t.token_length } }),
+        Frame::Stream(s) => json!({
+            "frame_type": "stream",
+            "stream_id": s.stream_id,
+            "offset": s.offset.unwrap_or(0),
+            "length": s.length.unwrap_or(s.stream_data.max_read_len),
+            "fin": s.fin,
+        }),
+        Frame::MaxData(m) => json!({ "frame_type": "max_data", "maximum": m.maximum_data }),
+        Frame::MaxStreamData(m) => json!({
+            "frame_type": "max_stream_data",
+            "stream_id": m.stream_id,
+            "maximum": m.maximum_stream_data,
+        }),
+        Frame::MaxStreams(m) => json!({
+            "frame_type": "max_streams",
+            "stream_type": stream_type(m.unidirectional),
+            "maximum": m.maximum_streams,
+        }),
+        Frame::DataBlocked(d) => json!({ "frame_type": "data_blocked", "limit": d.maximum_data }),
+        Frame::StreamDataBlocked(s) => json!({
+            "frame_type": "stream_data_blocked",
+            "stream_id": s.stream_id,
+            "limit": s.maximum_stream_data,
+        }),
+        Frame::StreamsBlocked(s) => json!({
+            "frame_type": "streams_blocked",
+            "stream_type": stream_type(s.unidirectional),
+            "limit": s.maximum_streams,
+        }),
+        Frame::NewConnectionId(n) => json!({
+            "frame_type": "new_connection_id",
+            "sequence_number": n.sequence_number,
+            "retire_prior_to": n.retire_prior_to,
+            "connection_id_length": n.length,
+        }),
+        Frame::RetireConnectionId(r) => json!({ "frame_type": "retire_connection_id", "sequence_number": r.sequence_number }),
+        Frame::PathChallenge(p) => json!({ "frame_type": "path_challenge", "data": format!("{:016x}", p.data) }),
+        Frame::PathResponse(p) => json!({ "frame_type": "path_response", "data": format!("{:016x}", p.data) }),
+        Frame::ConnectionClose(c) => {
+            // Only the transport variant carries the type of the offending frame.
+            let mut v = json!({
+                "frame_type": "connection_close",
+                "error_space": if c.frame_type.is_some() { "transport" } else { "application" },
+                "raw_error_code": c.error_code,
+                "reason": { "length": c.reason_phrase_length },
+            });
+            if let Some(ty) = c.frame_type {
+                v["trigger_frame_type"] = ty.into();
+            }
+            v
+        },
+        Frame::HandshakeDone(_) => json!({ "frame_type": "handshake_done" }),
+        Frame::Extension(e) => json!({ "frame_type": "unknown", "raw_frame_type": e.frame_type }),
+        _ => json!({ "frame_type": "unknown" }),
+    }
+}
+
+fn stream_type(unidirectional: bool) -> &'static str {
+    if unidirectional { "unidirectional" } else { "bidirectional" }
+}
@@ -0,0 +1,137 @@
+//! Runtime configuration of the logger by the application, through plugin
+//! controls. Changing the prefix or the format starts a new set of files.
+
+use pluginop_wasm::PluginEnv;
+use plugin_support::{input, poctl, Error, IntoCode, Result};
+
+use crate::{output::{self, Format}, restart, stop, PLUGIN_DATA};
+
+/// The received and sent frames.
+pub const FRAMES: u64 = 0x1;
+/// The recovery metrics updated when receiving ACK frames.
+pub const RECOVERY: u64 = 0x2;
+/// The recovery metrics sampled periodically with a timer.
+pub const TIMERS: u64 = 0x4;
+/// The transport parameters.
+pub const TRANSPORT_PARAMETERS: u64 = 0x8;
+/// The inputs that the protocol operations did not expect.
+pub const ERRORS: u64 = 0x10;
+pub const ALL: u64 = FRAMES | RECOVERY | TIMERS | TRANSPORT_PARAMETERS | ERRORS;
+
+pub struct Config {
+    /// The prefix of the file names, possibly holding directories.
+    pub prefix: String,
+    /// The enabled categories of events.
+    pub categories: u64,
+    pub format: Format,
+    /// Whether logging is enabled.
+    pub enabled: bool,
+    /// The size after which the output rotates to a new file.
+    pub file_size: usize,
+    /// The number of files after which the events are dropped.
+    pub max_files: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            prefix: "log-".to_string(),
+            categories: ALL,
+            format: Format::JsonSeq,
+            enabled: true,
+            file_size: output::FILE_SIZE,
+            max_files: output::MAX_FILES,
+        }
+    }
+}
+
+/// Set the prefix of the file names. Takes its length in bytes, followed by
+/// the UTF-8 prefix as 64-bit words.
+#[no_mangle]
+pub extern "C" fn plugin_control_80020(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let len: u64 = input(penv, 0)?;
+        let inputs = penv.get_inputs()?;
+        let mut bytes = poctl::words_to_bytes(&inputs, 1).ok_or(Error::BadInput(1))?;
+        if bytes.len() < len as usize {
+            return Err(Error::BadInput(0));
+        }
+        bytes.truncate(len as usize);
+        PLUGIN_DATA.get_mut().config.prefix = String::from_utf8(bytes).map_err(|_| Error::BadInput(1))?;
+        restart(penv)
+    })();
+    res.into_code()
+}
+
+/// Set the enabled categories of events, as a bitmask of [`FRAMES`],
+/// [`RECOVERY`], [`TIMERS`], [`TRANSPORT_PARAMETERS`] and [`ERRORS`].
+#[no_mangle]
+pub extern "C" fn plugin_control_80021(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let categories: u64 = input(penv, 0)?;
+        PLUGIN_DATA.get_mut().config.categories = categories & ALL;
+        Ok(())
+    })();
+    res.into_code()
+}
+
+/// Set the format of the trace, 0 for qlog JSON-SEQ, 1 for text and 2 for
+/// binary.
+#[no_mangle]
+pub extern "C" fn plugin_control_80022(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let format = match input::<u64>(penv, 0)? {
+            0 => Format::JsonSeq,
+            1 => Format::Text,
+            2 => Format::Binary,
+            _ => return Err(Error::BadInput(0)),
+        };
+        PLUGIN_DATA.get_mut().config.format = format;
+        restart(penv)
+    })();
+    res.into_code()
+}
+
+/// Start or stop logging. Once restarted, the events go to new files.
+#[no_mangle]
+pub extern "C" fn plugin_control_80023(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let enable: bool = input(penv, 0)?;
+        let pd = PLUGIN_DATA.get_mut();
+        match (pd.config.enabled, enable) {
+            (false, true) => {
+                pd.config.enabled = true;
+                restart(penv)
+            },
+            (true, false) => {
+                stop(penv);
+                pd.config.enabled = false;
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    })();
+    res.into_code()
+}
+
+/// Set the size after which the output rotates to a new file, and the number
+/// of files after which the events are dropped.
+#[no_mangle]
+pub extern "C" fn plugin_control_80024(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let file_size: u64 = input(penv, 0)?;
+        let max_files: u64 = input(penv, 1)?;
+        if max_files == 0 {
+            return Err(Error::BadInput(1));
+        }
+        let pd = PLUGIN_DATA.get_mut();
+        pd.config.file_size = file_size as usize;
+        pd.config.max_files = max_files.min(u32::MAX as u64) as u32;
+        if let Some(output) = pd.output.as_mut() {
+            output.file_size = pd.config.file_size;
+            output.max_files = pd.config.max_files;
+        }
+        Ok(())
+    })();
+    res.into_code()
+}
@@ -0,0 +1,60 @@
+//! The identity of the connection, written in the header of the trace such
+//! that the traces of both endpoints can be matched. The host does not
+//! expose the negotiated ALPN, so it is never logged.
+
+use pluginop_wasm::{PluginEnv, quic::{ConnectionField, Direction, Host, IDList, TransportParameterField}, Bytes};
+use logger_events::{Connection, ConnectionId, Event, TransportParameters};
+
+use crate::{config, record, PLUGIN_DATA};
+
+/// The largest number of connection IDs logged per endpoint, bounding the
+/// calls to the host.
+const MAX_CIDS: u64 = 16;
+
+/// The connection IDs in `direction`. The host hands out each of them as
+/// bytes.
+fn cids(penv: &mut PluginEnv, direction: Direction) -> Vec<ConnectionId> {
+    let min = penv.get_connection::<u64>(ConnectionField::ConnectionID(direction, IDList::MinID));
+    let max = penv.get_connection::<u64>(ConnectionField::ConnectionID(direction, IDList::MaxID));
+    let (Ok(min), Ok(max)) = (min, max) else { return Vec::new() };
+    (min..=max.min(min.saturating_add(MAX_CIDS - 1)))
+        .filter_map(|sequence_number| {
+            let b: Bytes = penv.get_connection(ConnectionField::ConnectionID(direction, IDList::Elem(sequence_number))).ok()?;
+            let id = penv.get_bytes(b.tag, b.max_read_len).ok()?;
+            Some(ConnectionId { sequence_number, id })
+        })
+        .collect()
+}
+
+fn parameters(penv: &PluginEnv, host: Host) -> TransportParameters {
+    TransportParameters {
+        ack_delay_exponent: penv.get_connection(ConnectionField::TransportParameter(host, TransportParameterField::AckDelayExponent)).ok(),
+    }
+}
+
+pub fn get(penv: &mut PluginEnv) -> Connection {
+    Connection {
+        version: penv.get_connection(ConnectionField::Version).ok(),
+        local_cids: cids(penv, Direction::Source),
+        peer_cids: cids(penv, Direction::Destination),
+        alpn: None,
+        local_parameters: parameters(penv, Host::Local),
+        peer_parameters: parameters(penv, Host::Remote),
+    }
+}
+
+/// Once the handshake completes, log what changed in the connection and write
+/// it in the header of the next files.
+pub fn established(penv: &mut PluginEnv) {
+    let connection = get(penv);
+    let pd = PLUGIN_DATA.get_mut();
+    if connection == pd.connection {
+        return;
+    }
+    pd.connection = connection.clone();
+    let header = pd.header();
+    if let Some(output) = pd.output.as_mut() {
+        output.set_header(&header);
+    }
+    record(penv, config::TRANSPORT_PARAMETERS, Event::ConnectionUpdated(connection));
+}
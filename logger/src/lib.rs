@@ -1,9 +1,13 @@
 use std::format;
 
-use pluginop_wasm::{PluginEnv, PluginCell, quic::ConnectionField, fd::FileDescriptor};
+use pluginop_wasm::{Duration, PluginEnv, PluginCell, PluginVal, UnixInstant, quic::{ACKFrame, ConnectionField, Frame, Host, QVal, TransportParameterField}};
 use lazy_static::lazy_static;
+use plugin_support::{frame, plugin_manifest, poctl::ids, varint, Error, IntoCode, Result};
+use logger_events::{Connection, Event, FrameEvent, Header, Record};
 
-use std::io::Write;
+use config::Config;
+use metrics::Metrics;
+use output::Output;
 
 extern crate wee_alloc;
 
@@ -12,61 +16,232 @@ extern crate wee_alloc;
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 struct PluginData {
-    logging_fd: Option<FileDescriptor>,
+    /// The output, if logging.
+    output: Option<Output>,
+    config: Config,
+    is_server: bool,
+    /// The reference time of the trace.
+    start: UnixInstant,
+    /// The recovery metrics logged last.
+    metrics: Metrics,
+    /// The connection logged last.
+    connection: Connection,
+}
+
+impl PluginData {
+    /// The header of the files.
+    fn header(&self) -> Header {
+        Header { is_server: self.is_server, reference_time: self.start.into(), connection: self.connection.clone() }
+    }
+}
+
+plugin_manifest! {
+    name: "logger",
+    frames: [],
+    transport_parameters: [],
+    timers: [metrics::SAMPLE_TIMER, output::FLUSH_TIMER],
+    plugin_controls: [ids::LOGGER_SET_PREFIX, ids::LOGGER_SET_CATEGORIES, ids::LOGGER_SET_FORMAT, ids::LOGGER_ENABLE, ids::LOGGER_SET_LIMITS],
+    calls: [],
 }
 
 lazy_static! {
-    static ref PLUGIN_DATA: PluginCell<PluginData> = PluginCell::new(PluginData {logging_fd: None});
+    static ref PLUGIN_DATA: PluginCell<PluginData> = PluginCell::new(PluginData {
+        output: None,
+        config: Config::default(),
+        is_server: false,
+        start: UnixInstant::at(0, 0),
+        metrics: Metrics::default(),
+        connection: Connection::default(),
+    });
 }
 
 // Initialize the plugin.
 #[no_mangle]
-pub extern fn init(penv: &mut PluginEnv) -> i64 {
+pub extern "C" fn init(penv: &mut PluginEnv) -> i64 {
     penv.print("Initializing logger plugin");
     penv.enable();
-    let role = if let Ok(v) = penv.get_connection(ConnectionField::IsServer) {
-        match v {
-            true => "server",
-            false => "client",
-        }
-    } else {
-        penv.print("aie 1");
-        return -1;
-    };
-    let start_time = match penv.get_unix_instant() {
-        Ok(ui) => ui,
-        Err(_) => {
-            penv.print("aie 2");
-            return -2;
-        },
-    };
-    match FileDescriptor::create(&format!("log-{}-{}{}.log", role, start_time.secs(), start_time.subsec_nanos())) {
-        Ok(fd) => {
-            PLUGIN_DATA.get_mut().logging_fd = Some(fd);
-            penv.print("All good");
-            0
-        }
-        Err(_) => {
-            penv.print("aie 3");
-            -3
-        },
+    let res: Result<()> = (|| {
+        let start = penv.get_unix_instant()?;
+        let pd = PLUGIN_DATA.get_mut();
+        pd.is_server = penv.get_connection(ConnectionField::IsServer)?;
+        pd.start = start;
+        pd.connection = connection::get(penv);
+        restart(penv)?;
+        metrics::arm_sampling(penv)?;
+        penv.set_timer(start + output::FLUSH_PERIOD, output::FLUSH_TIMER, output::FLUSH_TIMER)?;
+        Ok(())
+    })();
+    res.into_code()
+}
+
+/// Write the buffered events and stop logging.
+fn stop(penv: &PluginEnv) {
+    flush(penv);
+    PLUGIN_DATA.get_mut().output = None;
+}
+
+/// Log to a new set of files, named after the prefix, the role and the
+/// current time, if logging is enabled.
+fn restart(penv: &PluginEnv) -> Result<()> {
+    stop(penv);
+    let pd = PLUGIN_DATA.get_mut();
+    if !pd.config.enabled {
+        return Ok(());
     }
+    let role = logger_events::qlog::role(pd.is_server);
+    let now = penv.get_unix_instant()?;
+    let base = format!("{}{}-{}.{:09}", pd.config.prefix, role, now.secs(), now.subsec_nanos());
+    pd.output = Some(Output::new(base, pd.config.format, &pd.header(), pd.config.file_size, pd.config.max_files));
+    Ok(())
 }
 
-fn record(penv: &PluginEnv, s: &str) {
-    match PLUGIN_DATA.get_mut().logging_fd.as_mut() {
-        Some(fd) => {
-            let time = match penv.get_unix_instant() {
-                Ok(t) => t,
-                Err(_) => return,
-            };
-            match fd.write(&format!("{:?}: {}\n", time, s).as_bytes()) {
-                Ok(w) => penv.print(&format!("written {} bytes", w)),
-                Err(_) => penv.print("write failed"),
-            }
-        },
-        None => penv.print("FD does not exist!"),
+/// The time elapsed since the start of the trace.
+fn elapsed(penv: &PluginEnv) -> Option<Duration> {
+    penv.get_unix_instant().ok().map(|t| t.saturating_duration_since(PLUGIN_DATA.start))
+}
+
+/// Append the `event` to the trace, if logging its `category` of events.
+fn record(penv: &PluginEnv, category: u64, event: Event) {
+    if PLUGIN_DATA.config.categories & category == 0 {
+        return;
     }
+    let Some(time) = elapsed(penv) else { return };
+    let Some(output) = PLUGIN_DATA.get_mut().output.as_mut() else { return };
+    if output.push(&Record { time, event }).is_err() {
+        penv.print("write failed");
+    }
+}
+
+/// Write the buffered events, after reporting the dropped ones.
+fn flush(penv: &PluginEnv) {
+    let Some(time) = elapsed(penv) else { return };
+    let Some(output) = PLUGIN_DATA.get_mut().output.as_mut() else { return };
+    let dropped = output.take_dropped();
+    if dropped > 0 {
+        output.report(&Record { time, event: Event::EventsDropped { count: dropped } });
+    }
+    if output.flush().is_err() {
+        penv.print("write failed");
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn on_plugin_timeout_21(penv: &mut PluginEnv) -> i64 {
+    flush(penv);
+    let res: Result<()> = (|| {
+        let now = penv.get_unix_instant()?;
+        penv.set_timer(now + output::FLUSH_PERIOD, output::FLUSH_TIMER, output::FLUSH_TIMER)?;
+        Ok(())
+    })();
+    res.into_code()
+}
+
+/// Record a `recovery:metrics_updated` event if the recovery metrics changed
+/// since the last one.
+fn record_metrics(penv: &PluginEnv) {
+    let metrics = Metrics::get(penv);
+    let pd = PLUGIN_DATA.get_mut();
+    if let Some(data) = metrics.updated(&pd.metrics) {
+        pd.metrics = metrics;
+        record(penv, config::RECOVERY, Event::MetricsUpdated(data));
+    }
+}
+
+/// The default `ack_delay_exponent` transport parameter.
+const DEFAULT_ACK_DELAY_EXPONENT: u8 = 3;
+
+/// The type of the `input`, as recorded in `pluginop:unexpected_input`.
+fn input_type(input: &PluginVal) -> &'static str {
+    match input {
+        PluginVal::Bool(_) => "bool",
+        PluginVal::I32(_) => "i32",
+        PluginVal::I64(_) => "i64",
+        PluginVal::U32(_) => "u32",
+        PluginVal::U64(_) => "u64",
+        PluginVal::F32(_) => "f32",
+        PluginVal::F64(_) => "f64",
+        PluginVal::Usize(_) => "usize",
+        PluginVal::Bytes(_) => "bytes",
+        PluginVal::Duration(_) => "duration",
+        PluginVal::UNIXInstant(_) => "unix_instant",
+        PluginVal::SocketAddr(_) => "socket_addr",
+        PluginVal::QUIC(QVal::Header(_)) => "header",
+        PluginVal::QUIC(QVal::Frame(_)) => "frame",
+        PluginVal::QUIC(QVal::RcvInfo(_)) => "rcv_info",
+        PluginVal::QUIC(QVal::PacketNumberSpace(_)) => "packet_number_space",
+        PluginVal::QUIC(QVal::PacketType(_)) => "packet_type",
+    }
+}
+
+/// Record that `operation` failed with `e`, if it is about an unexpected
+/// input, instead of trapping and losing the plugin for the whole
+/// connection.
+fn unexpected(penv: &PluginEnv, operation: &str, e: Error) -> Error {
+    if let Error::BadInput(index) = e {
+        let found = penv.get_input::<PluginVal>(index).map_or("missing", |i| input_type(&i));
+        record(penv, config::ERRORS, Event::UnexpectedInput { operation: operation.to_string(), index, found: found.to_string() });
+    }
+    e
+}
+
+/// The ranges acknowledged by `ack`, from the largest. The ranges after the
+/// first one are read from its bytes, stopping at the first malformed one.
+fn acked_ranges(penv: &mut PluginEnv, ack: &ACKFrame) -> Vec<(u64, u64)> {
+    let Some(mut smallest) = ack.largest_acknowledged.checked_sub(ack.first_ack_range) else { return Vec::new() };
+    let mut ranges = vec![(smallest, ack.largest_acknowledged)];
+    if ack.ack_range_count == 0 {
+        return ranges;
+    }
+    let Ok(bytes) = penv.get_bytes(ack.ack_ranges.tag, ack.ack_ranges.max_read_len) else { return ranges };
+    let mut rest = bytes.as_slice();
+    for _ in 0..ack.ack_range_count {
+        let Ok((gap, len)) = varint::decode(rest) else { break };
+        let Ok((range, range_len)) = varint::decode(&rest[len..]) else { break };
+        rest = &rest[len + range_len..];
+        // RFC 9000, Section 19.3.1.
+        let Some(largest) = smallest.checked_sub(gap + 2) else { break };
+        let Some(s) = largest.checked_sub(range) else { break };
+        smallest = s;
+        ranges.push((smallest, largest));
+    }
+    ranges
+}
+
+/// Record what happened to the frame in input 0 of `operation`, whose ACK
+/// delay is encoded with the exponent of `host`.
+fn record_frame(penv: &mut PluginEnv, operation: &str, kind: FrameEvent, host: Host) -> Result<Frame> {
+    let frame = frame::frame(penv, 0).map_err(|e| unexpected(penv, operation, e))?;
+    let ack_delay_exponent = penv.get_connection::<u64>(ConnectionField::TransportParameter(host, TransportParameterField::AckDelayExponent))
+        .map_or(DEFAULT_ACK_DELAY_EXPONENT, |e| e as u8);
+    let acked_ranges = match &frame {
+        Frame::ACK(ack) => acked_ranges(penv, ack),
+        _ => Vec::new(),
+    };
+    record(penv, config::FRAMES, Event::Frame { kind, frame, ack_delay_exponent, acked_ranges });
+    Ok(frame)
+}
+
+/// Export `<prefix><type>` calling `handler` with its name for each frame type
+/// of RFC 9000.
+macro_rules! rfc9000_frames {
+    ($prefix:literal, $handler:path) => {
+        rfc9000_frames!(@ $prefix, $handler,
+            "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "a", "b", "c", "d", "e", "f",
+            "10", "11", "12", "13", "14", "15", "16", "17", "18", "19", "1a", "1b", "1c", "1d", "1e");
+    };
+    (@ $prefix:literal, $handler:path, $($hex:literal),*) => {$(
+        const _: () = {
+            #[export_name = concat!($prefix, $hex)]
+            pub extern "C" fn hook(penv: &mut PluginEnv) -> i64 {
+                $handler(penv, concat!($prefix, $hex))
+            }
+        };
+    )*};
 }
 
-pub mod process_frame;
\ No newline at end of file
+pub mod config;
+pub mod connection;
+pub mod metrics;
+pub mod process_frame;
+mod output;
+pub mod sent_frame;
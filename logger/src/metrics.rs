@@ -0,0 +1,82 @@
+//! The recovery metrics of the connection, logged as
+//! `recovery:metrics_updated` events.
+
+use pluginop_wasm::{Duration, PluginEnv, quic::{ConnectionField, RecoveryField}};
+use plugin_support::{IntoCode, Result};
+use logger_events::{Event, MetricsUpdate};
+
+use crate::{config, record, PLUGIN_DATA};
+
+/// The timer sampling the metrics.
+pub const SAMPLE_TIMER: u64 = 0x20;
+const SAMPLE_PERIOD: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Metrics {
+    pub min_rtt: Duration,
+    pub smoothed_rtt: Duration,
+    pub latest_rtt: Duration,
+    pub rtt_variance: Duration,
+    pub congestion_window: u64,
+    pub bytes_in_flight: u64,
+    pub ssthresh: u64,
+    pub max_tx_data: u64,
+}
+
+impl Metrics {
+    pub fn get(penv: &PluginEnv) -> Self {
+        Metrics {
+            min_rtt: penv.get_recovery(RecoveryField::MinRtt),
+            smoothed_rtt: penv.get_recovery(RecoveryField::SmoothedRtt),
+            latest_rtt: penv.get_recovery(RecoveryField::LatestRtt),
+            rtt_variance: penv.get_recovery(RecoveryField::Rttvar),
+            congestion_window: penv.get_recovery(RecoveryField::CongestionWindow),
+            bytes_in_flight: penv.get_recovery(RecoveryField::BytesInFlight),
+            ssthresh: penv.get_recovery(RecoveryField::Ssthresh),
+            max_tx_data: penv.get_connection(ConnectionField::MaxTxData).unwrap_or(0),
+        }
+    }
+
+    /// The update holding all the metrics.
+    pub fn all(&self) -> MetricsUpdate {
+        self.diff(None)
+    }
+
+    /// The update only holding the metrics that differ from `previous`, if
+    /// any.
+    pub fn updated(&self, previous: &Metrics) -> Option<MetricsUpdate> {
+        Some(self.diff(Some(previous))).filter(|u| *u != MetricsUpdate::default())
+    }
+
+    fn diff(&self, previous: Option<&Metrics>) -> MetricsUpdate {
+        fn changed<T: PartialEq>(v: T, prev: Option<T>) -> Option<T> {
+            if prev.as_ref() == Some(&v) { None } else { Some(v) }
+        }
+        MetricsUpdate {
+            min_rtt: changed(self.min_rtt, previous.map(|p| p.min_rtt)),
+            smoothed_rtt: changed(self.smoothed_rtt, previous.map(|p| p.smoothed_rtt)),
+            latest_rtt: changed(self.latest_rtt, previous.map(|p| p.latest_rtt)),
+            rtt_variance: changed(self.rtt_variance, previous.map(|p| p.rtt_variance)),
+            congestion_window: changed(self.congestion_window, previous.map(|p| p.congestion_window)),
+            bytes_in_flight: changed(self.bytes_in_flight, previous.map(|p| p.bytes_in_flight)),
+            ssthresh: changed(self.ssthresh, previous.map(|p| p.ssthresh)),
+            max_tx_data: changed(self.max_tx_data, previous.map(|p| p.max_tx_data)),
+        }
+    }
+}
+
+/// Arm the timer of the next sample.
+pub fn arm_sampling(penv: &mut PluginEnv) -> Result<()> {
+    let now = penv.get_unix_instant()?;
+    penv.set_timer(now + SAMPLE_PERIOD, SAMPLE_TIMER, SAMPLE_TIMER)?;
+    Ok(())
+}
+
+/// Record all the metrics, building their time series.
+#[no_mangle]
+pub extern "C" fn on_plugin_timeout_20(penv: &mut PluginEnv) -> i64 {
+    let metrics = Metrics::get(penv);
+    PLUGIN_DATA.get_mut().metrics = metrics;
+    record(penv, config::TIMERS, Event::MetricsUpdated(metrics.all()));
+    arm_sampling(penv).into_code()
+}
@@ -0,0 +1,180 @@
+//! Buffered output of the trace. Writing to a file crosses the WebAssembly
+//! boundary, so events are batched and written when the buffer fills up, on a
+//! timer or when the connection closes. The output rotates to a new file once
+//! the current one reaches its size limit, and drops the events once the
+//! maximum number of files is reached.
+
+use std::io::Write;
+
+use logger_events::{binary, qlog, Header, Record};
+use pluginop_wasm::{Duration, fd::FileDescriptor};
+use serde_json::Value;
+
+/// The number of buffered bytes triggering a write.
+const BUFFER_SIZE: usize = 16 * 1024;
+/// The default size after which the output rotates to a new file.
+pub const FILE_SIZE: usize = 16 * 1024 * 1024;
+/// The default number of files after which the events are dropped.
+pub const MAX_FILES: u32 = 8;
+/// The period at which the buffered events are written.
+pub const FLUSH_PERIOD: Duration = Duration::from_secs(1);
+/// The timer writing the buffered events.
+pub const FLUSH_TIMER: u64 = 0x21;
+
+/// The serialization of the trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// qlog JSON-SEQ records.
+    JsonSeq,
+    /// Human-readable lines, the event time being followed by its name and
+    /// data.
+    Text,
+    /// The compact encoding of `logger_events::binary`, which is much cheaper
+    /// to produce. The `logger-decode` tool converts it to qlog or CSV.
+    Binary,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::JsonSeq => "sqlog",
+            Format::Text => "log",
+            Format::Binary => "blog",
+        }
+    }
+
+    /// Serialize the `header` of the trace.
+    fn header(&self, header: &Header) -> Vec<u8> {
+        match self {
+            Format::JsonSeq => qlog::json_seq(&qlog::header(header)),
+            Format::Text => format!("# {}\n", qlog::header(header)).into_bytes(),
+            Format::Binary => binary::header(header),
+        }
+    }
+
+    /// Serialize a `record` of the trace.
+    fn record(&self, record: &Record) -> Vec<u8> {
+        match self {
+            Format::JsonSeq => qlog::json_seq(&qlog::event(record)),
+            Format::Text => format!("{} {} {}\n", Value::from(qlog::ms(record.time)), qlog::name(&record.event), qlog::data(&record.event)).into_bytes(),
+            Format::Binary => binary::record(record),
+        }
+    }
+}
+
+pub struct Output {
+    /// The name of the files, without the rotation index and extension.
+    base: String,
+    format: Format,
+    /// The record starting each file.
+    header: Vec<u8>,
+    /// The current file, only created when writing to it.
+    fd: Option<FileDescriptor>,
+    /// The number of created files.
+    files: u32,
+    /// The number of bytes written to the current file.
+    written: usize,
+    /// The size after which the output rotates to a new file.
+    pub file_size: usize,
+    /// The number of files after which the events are dropped.
+    pub max_files: u32,
+    buffer: Vec<u8>,
+    /// The number of events in `buffer`.
+    buffered: u64,
+    /// The number of dropped events not reported yet.
+    dropped: u64,
+}
+
+impl Output {
+    /// An output to files named after `base`, each starting with `header`.
+    pub fn new(base: String, format: Format, header: &Header, file_size: usize, max_files: u32) -> Self {
+        Output {
+            base,
+            format,
+            header: format.header(header),
+            fd: None,
+            files: 0,
+            written: 0,
+            file_size,
+            max_files,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            buffered: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Start the next files with `header`.
+    pub fn set_header(&mut self, header: &Header) {
+        self.header = self.format.header(header);
+    }
+
+    /// Whether the last file cannot hold more events.
+    fn is_full(&self) -> bool {
+        self.files >= self.max_files && self.written + self.buffer.len() >= self.file_size
+    }
+
+    /// Buffer the `record`, or drop it if the output is full.
+    pub fn push(&mut self, record: &Record) -> std::io::Result<()> {
+        if self.is_full() {
+            self.dropped += 1;
+            return Ok(());
+        }
+        self.buffer.extend_from_slice(&self.format.record(record));
+        self.buffered += 1;
+        if self.buffer.len() >= BUFFER_SIZE {
+            return self.flush();
+        }
+        Ok(())
+    }
+
+    /// Buffer the `record` even if the output is full. It is meant to report
+    /// the dropped events, so these do not go unnoticed.
+    pub fn report(&mut self, record: &Record) {
+        self.buffer.extend_from_slice(&self.format.record(record));
+    }
+
+    /// The number of events dropped since the last call.
+    pub fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// Write the buffered events, rotating the file if needed. The events are
+    /// dropped if the write fails.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let res = self.write();
+        if res.is_err() {
+            self.dropped += self.buffered;
+        }
+        self.buffer.clear();
+        self.buffered = 0;
+        if self.written >= self.file_size && self.files < self.max_files {
+            // The next write creates a new file.
+            self.fd = None;
+            self.written = 0;
+        }
+        res
+    }
+
+    fn write(&mut self) -> std::io::Result<()> {
+        let fd = match &mut self.fd {
+            Some(fd) => fd,
+            None => {
+                let name = match self.files {
+                    0 => format!("{}.{}", self.base, self.format.extension()),
+                    i => format!("{}.{}.{}", self.base, i, self.format.extension()),
+                };
+                let mut fd = FileDescriptor::create(&name)?;
+                self.files += 1;
+                fd.write_all(&self.header)?;
+                self.written = self.header.len();
+                self.fd.insert(fd)
+            },
+        };
+        fd.write_all(&self.buffer)?;
+        self.written += self.buffer.len();
+        Ok(())
+    }
+}
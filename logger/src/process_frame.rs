@@ -1,15 +1,20 @@
-use pluginop_wasm::{PluginEnv, quic::{QVal, Frame}};
-
-use crate::record;
-
-#[no_mangle]
-pub extern fn post_process_frame_2(penv: &PluginEnv) -> i64 {
-    penv.print("Called!");
-    let ack_frame = match penv.get_input(0) {
-        Ok(QVal::Frame(Frame::ACK(af))) => af,
-        _ => unreachable!(),
-    };
-    record(penv, &format!("Processed ACK frame with largest ack {}, ack delay {} and nb of gaps {}",
-                   ack_frame.largest_acknowledged, ack_frame.ack_delay, ack_frame.ack_range_count));
-    0
+//! Logging of the received frames, once processed by the host.
+
+use pluginop_wasm::{PluginEnv, quic::{Frame, Host}};
+use plugin_support::IntoCode;
+use logger_events::FrameEvent;
+
+use crate::{connection, flush, record_frame, record_metrics};
+
+fn frame_processed(penv: &mut PluginEnv, operation: &str) -> i64 {
+    record_frame(penv, operation, FrameEvent::Processed, Host::Remote)
+        .map(|f| match f {
+            Frame::ACK(_) => record_metrics(penv),
+            Frame::ConnectionClose(_) => flush(penv),
+            Frame::HandshakeDone(_) => connection::established(penv),
+            _ => {},
+        })
+        .into_code()
 }
+
+rfc9000_frames!("post_process_frame_", frame_processed);
@@ -0,0 +1,33 @@
+//! Logging of the sent frames and of their acknowledgment or loss.
+
+use pluginop_wasm::{PluginEnv, quic::{Frame, Host}};
+use plugin_support::{frame, IntoCode, Result};
+use logger_events::FrameEvent;
+
+use crate::{connection, flush, record_frame, unexpected};
+
+fn frame_written(penv: &mut PluginEnv, operation: &str) -> i64 {
+    record_frame(penv, operation, FrameEvent::Sent, Host::Local)
+        .map(|f| match f {
+            Frame::ConnectionClose(_) => flush(penv),
+            // The server confirms the handshake.
+            Frame::HandshakeDone(_) => connection::established(penv),
+            _ => {},
+        })
+        .into_code()
+}
+
+fn frame_notified(penv: &mut PluginEnv, operation: &str) -> i64 {
+    let res: Result<()> = (|| {
+        let kind = match frame::is_lost(penv).map_err(|e| unexpected(penv, operation, e))? {
+            true => FrameEvent::Lost,
+            false => FrameEvent::Acked,
+        };
+        record_frame(penv, operation, kind, Host::Local)?;
+        Ok(())
+    })();
+    res.into_code()
+}
+
+rfc9000_frames!("post_write_frame_", frame_written);
+rfc9000_frames!("post_notify_frame_", frame_notified);
@@ -1,23 +1,34 @@
-use pluginop_wasm::{PluginEnv, PluginVal, quic::{QVal, Frame, ConnectionField}};
+use pluginop_wasm::{PluginEnv, quic::{Frame, ConnectionField}};
+use plugin_support::{frame, plugin_manifest, Error, IntoCode, Result};
+
+plugin_manifest! {
+    name: "max-data",
+    frames: [],
+    transport_parameters: [],
+    timers: [],
+    plugin_controls: [],
+    calls: [],
+}
 
 #[no_mangle]
-pub extern fn init(penv: &mut PluginEnv) -> i64 {
+pub extern "C" fn init(penv: &mut PluginEnv) -> i64 {
     penv.enable();
     0
 }
 
 #[no_mangle]
-pub extern fn process_frame_10(penv: &mut PluginEnv) -> i64 {
+pub extern "C" fn process_frame_10(penv: &mut PluginEnv) -> i64 {
     penv.print("I'm in the plugin!!!");
-    let md_frame = match penv.get_input::<QVal>(0) {
-        Ok(QVal::Frame(Frame::MaxData(md))) => md,
-        _ => return -1,
-    };
-    let curr_max: u64 = if let Ok(v) = penv.get_connection(ConnectionField::MaxTxData) {v} else { return -2 };
-    if md_frame.maximum_data > curr_max {
-        if penv.set_connection(ConnectionField::MaxTxData, md_frame.maximum_data).is_err() {
-            return -3;
+    let res: Result<()> = (|| {
+        let md_frame = match frame::frame(penv, 0)? {
+            Frame::MaxData(md) => md,
+            _ => return Err(Error::BadInput(0)),
+        };
+        let curr_max: u64 = penv.get_connection(ConnectionField::MaxTxData)?;
+        if md_frame.maximum_data > curr_max {
+            penv.set_connection(ConnectionField::MaxTxData, md_frame.maximum_data)?;
         }
-    }
-    0
+        Ok(())
+    })();
+    res.into_code()
 }
\ No newline at end of file
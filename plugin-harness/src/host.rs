@@ -0,0 +1,184 @@
+//! The scripted fake host, i.e., the state of the QUIC connection exposed to
+//! the plugin.
+
+use std::collections::HashMap;
+
+use pluginop_common::{quic::{ConnectionField, RecoveryField, Registration}, Bytes, PluginVal};
+use serde::Serialize;
+use unix_time::Instant as UnixInstant;
+
+/// A timer set by the plugin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timer {
+    /// When the timer fires.
+    pub at: UnixInstant,
+    /// The identifier of the timer event, as used by `cancel_timer`.
+    pub id: u64,
+    /// The suffix of the `on_plugin_timeout_*` operation to call.
+    pub timer_id: u64,
+}
+
+/// The raw bytes behind a [`Bytes`] token.
+#[derive(Debug, Default)]
+struct Buffer {
+    data: Vec<u8>,
+    read: usize,
+    max_write_len: usize,
+}
+
+/// The host side of the plugin API. Tests script it before calling a
+/// protocol operation and inspect it afterwards.
+#[derive(Debug)]
+pub struct Host {
+    /// The inputs of the protocol operation being called.
+    pub inputs: Vec<PluginVal>,
+    /// The outputs saved by the last protocol operation.
+    pub outputs: Vec<PluginVal>,
+    /// The current time, as returned by `get_unix_instant`.
+    pub now: UnixInstant,
+    /// Whether the plugin called `enable`.
+    pub enabled: bool,
+    /// The registrations performed by the plugin.
+    pub registrations: Vec<Registration>,
+    /// The pending timers.
+    pub timers: Vec<Timer>,
+    /// The strings printed by the plugin.
+    pub printed: Vec<String>,
+    /// The plugin controls invoked by the plugin, with their inputs.
+    pub poctl_calls: Vec<(u64, Vec<PluginVal>)>,
+    /// The files created by the plugin, with their content.
+    pub files: Vec<(String, Vec<u8>)>,
+    connection: HashMap<Vec<u8>, PluginVal>,
+    recovery: HashMap<Vec<u8>, PluginVal>,
+    poctl: HashMap<u64, Vec<PluginVal>>,
+    bytes: HashMap<u64, Buffer>,
+    next_tag: u64,
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Host {
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            now: UnixInstant::at(1_700_000_000, 0),
+            enabled: false,
+            registrations: Vec::new(),
+            timers: Vec::new(),
+            printed: Vec::new(),
+            poctl_calls: Vec::new(),
+            files: Vec::new(),
+            connection: HashMap::new(),
+            recovery: HashMap::new(),
+            poctl: HashMap::new(),
+            bytes: HashMap::new(),
+            next_tag: 0,
+        }
+    }
+}
+
+/// Fields are not hashable, use their serialized form as key.
+fn key<T: Serialize>(field: &T) -> Vec<u8> {
+    postcard::to_allocvec(field).expect("serializable field")
+}
+
+impl Host {
+    /// Set the value returned by `get_connection` for `field`.
+    pub fn set_connection(&mut self, field: ConnectionField, v: impl Into<PluginVal>) {
+        self.connection.insert(key(&field), v.into());
+    }
+
+    /// The current value of the connection `field`, if any.
+    pub fn connection(&self, field: ConnectionField) -> Option<PluginVal> {
+        self.connection.get(&key(&field)).copied()
+    }
+
+    /// Set the value returned by `get_recovery` for `field`. Recovery fields
+    /// that were never set read as zero.
+    pub fn set_recovery(&mut self, field: RecoveryField, v: impl Into<PluginVal>) {
+        self.recovery.insert(key(&field), v.into());
+    }
+
+    /// The current value of the recovery `field`, if any.
+    pub fn recovery(&self, field: RecoveryField) -> Option<PluginVal> {
+        self.recovery.get(&key(&field)).copied()
+    }
+
+    /// Make the plugin control `id` of another plugin succeed with `outputs`.
+    /// Plugin controls without response fail.
+    pub fn on_poctl(&mut self, id: u64, outputs: Vec<PluginVal>) {
+        self.poctl.insert(id, outputs);
+    }
+
+    /// Create bytes that the plugin can read `data` from and write at most
+    /// `max_write_len` bytes to.
+    pub fn new_bytes(&mut self, data: &[u8], max_write_len: usize) -> Bytes {
+        let tag = self.next_tag;
+        self.next_tag += 1;
+        self.bytes.insert(tag, Buffer { data: data.to_vec(), read: 0, max_write_len });
+        Bytes { tag, max_read_len: data.len() as u64, max_write_len: max_write_len as u64 }
+    }
+
+    /// The content of the bytes with `tag`, including what the plugin wrote.
+    pub fn bytes(&self, tag: u64) -> &[u8] {
+        &self.bytes[&tag].data
+    }
+
+    /// The number of bytes with `tag` that the plugin did not read.
+    pub fn unread(&self, tag: u64) -> usize {
+        let b = &self.bytes[&tag];
+        b.data.len() - b.read
+    }
+
+    /// The pending timer with the given `id`, if any.
+    pub fn timer(&self, id: u64) -> Option<Timer> {
+        self.timers.iter().find(|t| t.id == id).copied()
+    }
+
+    pub(crate) fn connection_raw(&self, field: &[u8]) -> Option<PluginVal> {
+        self.connection.get(field).copied()
+    }
+
+    pub(crate) fn set_connection_raw(&mut self, field: Vec<u8>, v: PluginVal) {
+        self.connection.insert(field, v);
+    }
+
+    pub(crate) fn recovery_raw(&self, field: &[u8]) -> Option<PluginVal> {
+        self.recovery.get(field).copied()
+    }
+
+    pub(crate) fn set_recovery_raw(&mut self, field: Vec<u8>, v: PluginVal) {
+        self.recovery.insert(field, v);
+    }
+
+    pub(crate) fn poctl(&mut self, id: u64, inputs: Vec<PluginVal>) -> Option<Vec<PluginVal>> {
+        self.poctl_calls.push((id, inputs));
+        self.poctl.get(&id).cloned()
+    }
+
+    pub(crate) fn read_bytes(&mut self, tag: u64, len: usize) -> Option<Vec<u8>> {
+        let b = self.bytes.get_mut(&tag)?;
+        let end = (b.read + len).min(b.data.len());
+        let out = b.data[b.read..end].to_vec();
+        b.read = end;
+        Some(out)
+    }
+
+    pub(crate) fn write_bytes(&mut self, tag: u64, data: &[u8]) -> Option<usize> {
+        let b = self.bytes.get_mut(&tag)?;
+        let len = data.len().min(b.max_write_len);
+        b.data.extend_from_slice(&data[..len]);
+        b.max_write_len -= len;
+        Some(len)
+    }
+
+    pub(crate) fn set_timer(&mut self, at: UnixInstant, id: u64, timer_id: u64) {
+        self.timers.retain(|t| t.id != id);
+        self.timers.push(Timer { at, id, timer_id });
+    }
+
+    pub(crate) fn cancel_timer(&mut self, id: u64) -> bool {
+        let len = self.timers.len();
+        self.timers.retain(|t| t.id != id);
+        self.timers.len() != len
+    }
+}
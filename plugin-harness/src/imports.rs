@@ -0,0 +1,216 @@
+//! The functions imported by the plugins, as declared by `pluginop-wasm`.
+
+use pluginop_common::{quic::Registration, PluginVal};
+use serde::{de::DeserializeOwned, Serialize};
+use unix_time::Instant as UnixInstant;
+use wasmi::{Caller, Extern, Linker, Memory};
+
+use crate::Host;
+
+const OK: i64 = 0;
+const ERR: i64 = -1;
+
+fn memory(caller: &Caller<'_, Host>) -> Memory {
+    caller.get_export("memory").and_then(Extern::into_memory).expect("plugin exports its memory")
+}
+
+fn read(caller: &Caller<'_, Host>, ptr: u32, len: u32) -> Option<Vec<u8>> {
+    let mut buf = vec![0; len as usize];
+    memory(caller).read(caller, ptr as usize, &mut buf).ok()?;
+    Some(buf)
+}
+
+fn decode<T: DeserializeOwned>(caller: &Caller<'_, Host>, ptr: u32, len: u32) -> Option<T> {
+    postcard::from_bytes(&read(caller, ptr, len)?).ok()
+}
+
+fn write(caller: &mut Caller<'_, Host>, ptr: u32, len: u32, data: &[u8]) -> i64 {
+    if data.len() > len as usize {
+        return ERR;
+    }
+    match memory(caller).write(caller, ptr as usize, data) {
+        Ok(()) => OK,
+        Err(_) => ERR,
+    }
+}
+
+fn encode<T: Serialize + ?Sized>(caller: &mut Caller<'_, Host>, ptr: u32, len: u32, v: &T) -> i64 {
+    match postcard::to_allocvec(v) {
+        Ok(data) => write(caller, ptr, len, &data),
+        Err(_) => ERR,
+    }
+}
+
+/// `get_recovery` deserializes the field value itself instead of a
+/// [`PluginVal`].
+fn encode_raw(caller: &mut Caller<'_, Host>, ptr: u32, len: u32, v: &PluginVal) -> i64 {
+    match v {
+        PluginVal::Bool(v) => encode(caller, ptr, len, v),
+        PluginVal::I32(v) => encode(caller, ptr, len, v),
+        PluginVal::I64(v) => encode(caller, ptr, len, v),
+        PluginVal::U32(v) => encode(caller, ptr, len, v),
+        PluginVal::U64(v) | PluginVal::Usize(v) => encode(caller, ptr, len, v),
+        PluginVal::F32(v) => encode(caller, ptr, len, v),
+        PluginVal::F64(v) => encode(caller, ptr, len, v),
+        PluginVal::Bytes(v) => encode(caller, ptr, len, v),
+        PluginVal::Duration(v) => encode(caller, ptr, len, v),
+        PluginVal::UNIXInstant(v) => encode(caller, ptr, len, v),
+        PluginVal::SocketAddr(v) => encode(caller, ptr, len, v),
+        PluginVal::QUIC(v) => encode(caller, ptr, len, v),
+    }
+}
+
+fn code(ok: bool) -> i64 {
+    if ok { OK } else { ERR }
+}
+
+pub(crate) fn define(linker: &mut Linker<Host>) -> Result<(), wasmi::Error> {
+    linker.func_wrap("env", "save_output_from_plugin", |mut caller: Caller<'_, Host>, ptr: u32, len: u32| -> i64 {
+        match decode::<PluginVal>(&caller, ptr, len) {
+            Some(v) => {
+                caller.data_mut().outputs.push(v);
+                OK
+            },
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "save_outputs_from_plugin", |mut caller: Caller<'_, Host>, ptr: u32, len: u32| -> i64 {
+        match decode::<Vec<PluginVal>>(&caller, ptr, len) {
+            Some(v) => {
+                caller.data_mut().outputs = v;
+                OK
+            },
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "print_from_plugin", |mut caller: Caller<'_, Host>, ptr: u32, len: u32| {
+        if let Some(b) = read(&caller, ptr, len) {
+            let s = String::from_utf8_lossy(&b).into_owned();
+            caller.data_mut().printed.push(s);
+        }
+    })?;
+    linker.func_wrap("env", "get_connection_from_plugin", |mut caller: Caller<'_, Host>, field_ptr: u32, field_len: u32, res_ptr: u32, res_len: u32| -> i64 {
+        let v = read(&caller, field_ptr, field_len).and_then(|f| caller.data().connection_raw(&f));
+        match v {
+            Some(v) => encode(&mut caller, res_ptr, res_len, &v),
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "set_connection_from_plugin", |mut caller: Caller<'_, Host>, field_ptr: u32, field_len: u32, value_ptr: u32, value_len: u32| -> i64 {
+        let field = read(&caller, field_ptr, field_len);
+        let value = decode::<PluginVal>(&caller, value_ptr, value_len);
+        match (field, value) {
+            (Some(f), Some(v)) => {
+                caller.data_mut().set_connection_raw(f, v);
+                OK
+            },
+            _ => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "get_recovery_from_plugin", |mut caller: Caller<'_, Host>, field_ptr: u32, field_len: u32, res_ptr: u32, res_len: u32| -> i64 {
+        match read(&caller, field_ptr, field_len).and_then(|f| caller.data().recovery_raw(&f)) {
+            Some(v) => encode_raw(&mut caller, res_ptr, res_len, &v),
+            // Unset fields read as zero, whatever their type.
+            None => write(&mut caller, res_ptr, res_len, &[0; 16]),
+        }
+    })?;
+    linker.func_wrap("env", "set_recovery_from_plugin", |mut caller: Caller<'_, Host>, field_ptr: u32, field_len: u32, value_ptr: u32, value_len: u32| -> i64 {
+        let field = read(&caller, field_ptr, field_len);
+        let value = decode::<PluginVal>(&caller, value_ptr, value_len);
+        match (field, value) {
+            (Some(f), Some(v)) => {
+                caller.data_mut().set_recovery_raw(f, v);
+                OK
+            },
+            _ => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "get_input_from_plugin", |mut caller: Caller<'_, Host>, index: u32, res_ptr: u32, res_len: u32| -> i64 {
+        match caller.data().inputs.get(index as usize).copied() {
+            Some(v) => encode(&mut caller, res_ptr, res_len, &v),
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "get_inputs_from_plugin", |mut caller: Caller<'_, Host>, res_ptr: u32, res_len: u32| -> i64 {
+        let inputs = caller.data().inputs.clone();
+        encode(&mut caller, res_ptr, res_len, &inputs)
+    })?;
+    linker.func_wrap("env", "get_bytes_from_plugin", |mut caller: Caller<'_, Host>, tag: u64, len: u64, res_ptr: u32, res_len: u32| -> i64 {
+        let len = len.min(res_len as u64) as usize;
+        match caller.data_mut().read_bytes(tag, len) {
+            Some(b) => match write(&mut caller, res_ptr, res_len, &b) {
+                OK => b.len() as i64,
+                e => e,
+            },
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "put_bytes_from_plugin", |mut caller: Caller<'_, Host>, tag: u64, ptr: u32, len: u32| -> i64 {
+        match read(&caller, ptr, len) {
+            Some(b) => caller.data_mut().write_bytes(tag, &b).map(|l| l as i64).unwrap_or(ERR),
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "register_from_plugin", |mut caller: Caller<'_, Host>, ptr: u32, len: u32| -> i64 {
+        match decode::<Registration>(&caller, ptr, len) {
+            Some(r) => {
+                caller.data_mut().registrations.push(r);
+                OK
+            },
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "set_timer_from_plugin", |mut caller: Caller<'_, Host>, ts_ptr: u32, ts_len: u32, id: u64, timer_id: u64| -> i64 {
+        match decode::<UnixInstant>(&caller, ts_ptr, ts_len) {
+            Some(ts) => {
+                caller.data_mut().set_timer(ts, id, timer_id);
+                OK
+            },
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "cancel_timer_from_plugin", |mut caller: Caller<'_, Host>, id: u64| -> i64 {
+        code(caller.data_mut().cancel_timer(id))
+    })?;
+    linker.func_wrap("env", "get_unix_instant_from_plugin", |mut caller: Caller<'_, Host>, res_ptr: u32, res_len: u32| -> i64 {
+        let now = caller.data().now;
+        encode(&mut caller, res_ptr, res_len, &now)
+    })?;
+    linker.func_wrap("env", "enable_from_plugin", |mut caller: Caller<'_, Host>| {
+        caller.data_mut().enabled = true;
+    })?;
+    linker.func_wrap("env", "poctl_from_plugin", |mut caller: Caller<'_, Host>, id: u64, input_ptr: u32, input_len: u32, res_ptr: u32, res_len: u32| -> i64 {
+        let inputs = match decode::<Vec<PluginVal>>(&caller, input_ptr, input_len) {
+            Some(i) => i,
+            None => return ERR,
+        };
+        match caller.data_mut().poctl(id, inputs) {
+            Some(outputs) => encode(&mut caller, res_ptr, res_len, &outputs),
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "create_file_from_plugin", |mut caller: Caller<'_, Host>, path_ptr: u32, path_len: u32| -> i64 {
+        match read(&caller, path_ptr, path_len) {
+            Some(p) => {
+                let files = &mut caller.data_mut().files;
+                files.push((String::from_utf8_lossy(&p).into_owned(), Vec::new()));
+                files.len() as i64 - 1
+            },
+            None => ERR,
+        }
+    })?;
+    linker.func_wrap("env", "write_file_from_plugin", |mut caller: Caller<'_, Host>, fd: i64, ptr: u32, len: u32| -> i64 {
+        let b = match read(&caller, ptr, len) {
+            Some(b) => b,
+            None => return ERR,
+        };
+        match caller.data_mut().files.get_mut(fd as usize) {
+            Some((_, content)) => {
+                content.extend_from_slice(&b);
+                b.len() as i64
+            },
+            None => ERR,
+        }
+    })?;
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! A native harness running the plugins of this repository in a WebAssembly
+//! runtime, on top of a scripted fake [`Host`].
+//!
+//! Tests load a plugin, script the host state (inputs, connection and
+//! recovery fields, responses of plugin controls), call a protocol operation
+//! and then check its return code, its outputs and the host state.
+//!
+//! ```ignore
+//! let mut p = Plugin::load("privacy-padding");
+//! assert_eq!(p.call("init", &[]), 0);
+//! assert!(p.host().enabled);
+//! ```
+
+use std::path::Path;
+
+use wasmi::{Engine, Instance, Linker, Module, Store};
+
+pub use pluginop_common::{quic, Bytes, PluginVal};
+pub use std::time::Duration;
+pub use unix_time::Instant as UnixInstant;
+
+pub use host::{Host, Timer};
+
+/// A plugin instantiated on top of a fresh [`Host`].
+pub struct Plugin {
+    store: Store<Host>,
+    instance: Instance,
+}
+
+impl Plugin {
+    /// Compile the plugin crate `name` and instantiate it.
+    pub fn load(name: &str) -> Self {
+        Self::from_file(&wasm::build(name))
+    }
+
+    /// Instantiate the plugin from its WebAssembly file.
+    pub fn from_file(path: &Path) -> Self {
+        let bytecode = std::fs::read(path).unwrap_or_else(|e| panic!("cannot read {}: {e}", path.display()));
+        Self::new(&bytecode).unwrap_or_else(|e| panic!("cannot instantiate {}: {e}", path.display()))
+    }
+
+    /// Instantiate the plugin from its WebAssembly bytecode.
+    pub fn new(bytecode: &[u8]) -> Result<Self, wasmi::Error> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, bytecode)?;
+        let mut store = Store::new(&engine, Host::default());
+        let mut linker = Linker::new(&engine);
+        imports::define(&mut linker)?;
+        let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+        Ok(Plugin { store, instance })
+    }
+
+    pub fn host(&self) -> &Host {
+        self.store.data()
+    }
+
+    pub fn host_mut(&mut self) -> &mut Host {
+        self.store.data_mut()
+    }
+
+    /// The names of all the functions exported by the plugin.
+    pub fn functions(&self) -> Vec<String> {
+        self.instance.exports(&self.store)
+            .filter(|e| e.clone().into_func().is_some())
+            .map(|e| e.name().to_string())
+            .collect()
+    }
+
+    /// Whether the plugin exports the protocol operation `name`.
+    pub fn exports(&self, name: &str) -> bool {
+        self.instance.get_func(&self.store, name).is_some()
+    }
+
+    /// Call the protocol operation `name` with `inputs`, returning its code.
+    /// The outputs are then available with [`Plugin::outputs`].
+    ///
+    /// Panics if the operation is not exported or traps.
+    pub fn call(&mut self, name: &str, inputs: &[PluginVal]) -> i64 {
+        self.try_call(name, inputs).unwrap_or_else(|e| panic!("{name} failed: {e}"))
+    }
+
+    /// Same as [`Plugin::call`], but returns the trap instead of panicking.
+    pub fn try_call(&mut self, name: &str, inputs: &[PluginVal]) -> Result<i64, wasmi::Error> {
+        let host = self.store.data_mut();
+        host.inputs = inputs.to_vec();
+        host.outputs.clear();
+        let po = self.instance.get_typed_func::<u32, i64>(&self.store, name)?;
+        // The environment pointer is never dereferenced by the plugin API.
+        po.call(&mut self.store, 0)
+    }
+
+    /// The outputs saved by the last protocol operation.
+    pub fn outputs(&self) -> &[PluginVal] {
+        &self.host().outputs
+    }
+
+    /// Move the time of the host to `now` and call `on_plugin_timeout_*` for
+    /// each expired timer, in order. Returns the codes of the calls.
+    pub fn advance(&mut self, now: UnixInstant) -> Vec<i64> {
+        self.host_mut().now = now;
+        let mut codes = Vec::new();
+        loop {
+            let host = self.host_mut();
+            host.timers.sort_by_key(|t| t.at);
+            match host.timers.first() {
+                Some(t) if t.at <= now => {
+                    let t = host.timers.remove(0);
+                    codes.push(self.call(&format!("on_plugin_timeout_{:x}", t.timer_id), &[PluginVal::U64(t.id)]));
+                },
+                _ => return codes,
+            }
+        }
+    }
+}
+
+mod host;
+mod imports;
+mod wasm;
@@ -0,0 +1,36 @@
+//! Compilation of the plugins to WebAssembly.
+
+use std::{collections::HashMap, path::{Path, PathBuf}, process::Command, sync::Mutex};
+
+static BUILT: Mutex<Option<HashMap<String, PathBuf>>> = Mutex::new(None);
+
+fn workspace_root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().expect("harness is in the workspace")
+}
+
+/// Compile the plugin crate `name` in release mode and return the path of its
+/// WebAssembly file. Each plugin is compiled at most once per process, in a
+/// dedicated target directory to not wait on the lock of the calling cargo.
+pub fn build(name: &str) -> PathBuf {
+    let mut built = BUILT.lock().unwrap_or_else(|e| e.into_inner());
+    let built = built.get_or_insert_with(HashMap::new);
+    if let Some(p) = built.get(name) {
+        return p.clone();
+    }
+    let target_dir = workspace_root().join("target").join("plugin-harness");
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(cargo)
+        .current_dir(workspace_root())
+        .args(["build", "--release", "--target", "wasm32-unknown-unknown", "-p", name, "--target-dir"])
+        .arg(&target_dir)
+        .output()
+        .expect("cargo can be run");
+    assert!(
+        output.status.success(),
+        "failed to compile {name} to WebAssembly, is the wasm32-unknown-unknown target installed?\n{}",
+        String::from_utf8_lossy(&output.stderr),
+    );
+    let path = target_dir.join("wasm32-unknown-unknown").join("release").join(format!("{}.wasm", name.replace('-', "_")));
+    built.insert(name.to_string(), path.clone());
+    path
+}
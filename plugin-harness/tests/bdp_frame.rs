@@ -0,0 +1,265 @@
+use plugin_harness::{quic::{ConnectionField, ExtensionFrame, Frame, PacketType, QVal, RecoveryField}, Duration, Plugin, PluginVal};
+
+const SECRET: [u64; 2] = [0x0123456789abcdef, 0xfedcba9876543210];
+
+fn bdp_frame() -> PluginVal {
+    PluginVal::QUIC(QVal::Frame(Frame::Extension(ExtensionFrame { frame_type: 0xbd, tag: 0 })))
+}
+
+fn endpoint(is_server: bool) -> Plugin {
+    let mut p = Plugin::load("bdp-frame");
+    assert_eq!(p.call("init", &[]), 0);
+    p.host_mut().set_connection(ConnectionField::IsServer, is_server);
+    if is_server {
+        let mut inputs = vec![Duration::from_secs(3600).into()];
+        inputs.extend(SECRET.iter().map(|w| PluginVal::U64(*w)));
+        assert_eq!(p.call("plugin_control_80002", &inputs), 0);
+    }
+    p
+}
+
+/// Write the pending BDP frame of `p` and return its content, without type.
+fn write_frame(p: &mut Plugin) -> Vec<u8> {
+    let short = [PluginVal::QUIC(QVal::PacketType(PacketType::Short)), PluginVal::U64(0), false.into(), 1200usize.into()];
+    assert_eq!(p.call("should_send_frame_bd", &short), 0);
+    assert_eq!(p.outputs(), &[true.into()]);
+    assert_eq!(p.call("prepare_frame_bd", &[]), 0);
+    assert_eq!(p.outputs(), &[bdp_frame()]);
+    let out = p.host_mut().new_bytes(&[], 1200);
+    assert_eq!(p.call("write_frame_bd", &[bdp_frame(), out.into()]), 0);
+    let written = p.host().bytes(out.tag).to_vec();
+    assert_eq!(&written[..2], &[0x40, 0xbd]);
+    written[2..].to_vec()
+}
+
+fn receive_frame(p: &mut Plugin, content: &[u8]) -> i64 {
+    let bytes = p.host_mut().new_bytes(content, 0);
+    assert_eq!(p.call("parse_frame_bd", &[bytes.into()]), 0);
+    assert_eq!(p.host().unread(bytes.tag), 0);
+    p.call("process_frame_bd", &[bdp_frame()])
+}
+
+fn set_path(p: &mut Plugin, cwin: usize, rtt: Duration) {
+    let host = p.host_mut();
+    host.set_recovery(RecoveryField::CongestionWindow, cwin);
+    host.set_recovery(RecoveryField::SmoothedRtt, rtt);
+    host.set_recovery(RecoveryField::MinRtt, rtt);
+}
+
+#[test]
+fn token_round_trip_resumes_cwin() {
+    // The server of a first connection issues a token to the client.
+    let mut server = endpoint(true);
+    set_path(&mut server, 100_000, Duration::from_millis(50));
+    assert_eq!(server.call("plugin_control_80000", &[]), 0);
+    let token = write_frame(&mut server);
+
+    let mut client = endpoint(false);
+    assert_eq!(receive_frame(&mut client, &token), 0);
+    assert_eq!(client.call("plugin_control_80003", &[]), 0);
+    let saved = client.outputs().to_vec();
+    assert_eq!(saved[0], PluginVal::U64(100_000));
+
+    // The client of the next connection sends it back.
+    let mut client = endpoint(false);
+    assert_eq!(client.call("plugin_control_80000", &saved), 0);
+    assert_eq!(client.call("decode_transport_parameter_bf", &[]), 0);
+    assert_eq!(write_frame(&mut client), token);
+
+    // The new server validates the path, then ramps up.
+    let mut server = endpoint(true);
+    set_path(&mut server, 12_000, Duration::from_millis(50));
+    assert_eq!(receive_frame(&mut server, &token), 0);
+    let timer = server.host().timer(0xbd).expect("careful resume started");
+    let mut now = timer.at;
+    let mut last = 12_000;
+    for _ in 0..4 {
+        assert_eq!(server.advance(now), vec![0]);
+        let cwin = match server.host().recovery(RecoveryField::CongestionWindow) {
+            Some(PluginVal::Usize(c)) => c,
+            v => panic!("unexpected cwin {v:?}"),
+        };
+        assert!(cwin > last);
+        last = cwin;
+        now += Duration::from_millis(50);
+    }
+    assert_eq!(last, 100_000);
+    assert!(server.host().timers.is_empty());
+}
+
+#[test]
+fn forged_token_is_ignored() {
+    let mut server = endpoint(true);
+    set_path(&mut server, 100_000, Duration::from_millis(50));
+    assert_eq!(server.call("plugin_control_80000", &[]), 0);
+    let mut token = write_frame(&mut server);
+    // Increase the congestion window.
+    token[0] ^= 0x01;
+
+    let mut server = endpoint(true);
+    set_path(&mut server, 12_000, Duration::from_millis(50));
+    assert_eq!(receive_frame(&mut server, &token), 0);
+    assert!(server.host().timers.is_empty());
+}
+
+#[test]
+fn loss_during_ramp_falls_back() {
+    let mut server = endpoint(true);
+    set_path(&mut server, 100_000, Duration::from_millis(50));
+    assert_eq!(server.call("plugin_control_80000", &[]), 0);
+    let token = write_frame(&mut server);
+
+    let mut server = endpoint(true);
+    set_path(&mut server, 12_000, Duration::from_millis(50));
+    assert_eq!(receive_frame(&mut server, &token), 0);
+    let at = server.host().timer(0xbd).unwrap().at;
+    assert_eq!(server.advance(at), vec![0]);
+    server.host_mut().set_recovery(RecoveryField::CongestionWindow, 6_000usize);
+    let at = server.host().timer(0xbd).unwrap().at;
+    assert_eq!(server.advance(at), vec![0]);
+    assert!(server.host().timers.is_empty());
+    assert_eq!(server.host().recovery(RecoveryField::CongestionWindow), Some(PluginVal::Usize(6_000)));
+}
+
+#[test]
+fn fall_back_restores_the_cwin_before_the_ramp() {
+    let mut server = endpoint(true);
+    set_path(&mut server, 100_000, Duration::from_millis(50));
+    assert_eq!(server.call("plugin_control_80000", &[]), 0);
+    let token = write_frame(&mut server);
+
+    let mut server = endpoint(true);
+    set_path(&mut server, 12_000, Duration::from_millis(50));
+    assert_eq!(receive_frame(&mut server, &token), 0);
+    let at = server.host().timer(0xbd).unwrap().at;
+    assert_eq!(server.advance(at), vec![0]);
+    assert_eq!(server.host().recovery(RecoveryField::CongestionWindow), Some(PluginVal::Usize(34_000)));
+    // The host halves the ramped window on a loss, still above the validated one.
+    server.host_mut().set_recovery(RecoveryField::CongestionWindow, 17_000usize);
+    let at = server.host().timer(0xbd).unwrap().at;
+    assert_eq!(server.advance(at), vec![0]);
+    assert!(server.host().timers.is_empty());
+    assert_eq!(server.host().recovery(RecoveryField::CongestionWindow), Some(PluginVal::Usize(12_000)));
+}
+
+#[test]
+fn server_without_secret_ignores_tokens() {
+    // An untagged record, as a client could forge it.
+    let mut client = endpoint(false);
+    let inputs = [PluginVal::U64(100_000), Duration::from_millis(50).into(), client.host().now.into(), Duration::from_secs(3600).into()];
+    assert_eq!(client.call("plugin_control_80000", &inputs), 0);
+    let token = write_frame(&mut client);
+
+    let mut server = Plugin::load("bdp-frame");
+    assert_eq!(server.call("init", &[]), 0);
+    server.host_mut().set_connection(ConnectionField::IsServer, true);
+    set_path(&mut server, 12_000, Duration::from_millis(50));
+    assert_eq!(receive_frame(&mut server, &token), 0);
+    assert!(server.host().timers.is_empty());
+    assert_eq!(server.host().recovery(RecoveryField::CongestionWindow), Some(PluginVal::Usize(12_000)));
+}
+
+#[test]
+fn captured_bdp_is_smoothed_and_signed() {
+    let mut server = endpoint(true);
+    set_path(&mut server, 100_000, Duration::from_millis(50));
+    assert_eq!(server.call("post_process_frame_2", &[]), 0);
+    // A transient spike only moves the estimate a little.
+    server.host_mut().set_recovery(RecoveryField::CongestionWindow, 400_000usize);
+    assert_eq!(server.call("post_process_frame_2", &[]), 0);
+    // Not sampled while queues build up.
+    server.host_mut().set_recovery(RecoveryField::CongestionWindow, 1_000_000usize);
+    server.host_mut().set_recovery(RecoveryField::SmoothedRtt, Duration::from_millis(150));
+    assert_eq!(server.call("post_process_frame_2", &[]), 0);
+    assert_eq!(server.call("plugin_control_80005", &[]), 0);
+    let saved = server.outputs().to_vec();
+    assert_eq!(saved[0], PluginVal::U64(137_500));
+
+    // The next server accepts it.
+    let mut client = endpoint(false);
+    assert_eq!(client.call("plugin_control_80000", &saved), 0);
+    let token = write_frame(&mut client);
+    let mut server = endpoint(true);
+    set_path(&mut server, 12_000, Duration::from_millis(50));
+    assert_eq!(receive_frame(&mut server, &token), 0);
+    assert!(server.host().timer(0xbd).is_some());
+}
+
+#[test]
+fn server_sends_the_captured_bdp_when_closing() {
+    let mut server = endpoint(true);
+    set_path(&mut server, 100_000, Duration::from_millis(50));
+    assert_eq!(server.call("post_process_frame_2", &[]), 0);
+    // The closing connection is sampled one last time.
+    server.host_mut().set_recovery(RecoveryField::CongestionWindow, 400_000usize);
+    let closing = [PluginVal::QUIC(QVal::PacketType(PacketType::Short)), PluginVal::U64(0), true.into(), 1200usize.into()];
+    assert_eq!(server.call("should_send_frame_bd", &closing), 0);
+    assert_eq!(server.outputs(), &[true.into()]);
+    assert_eq!(server.call("prepare_frame_bd", &[]), 0);
+    let out = server.host_mut().new_bytes(&[], 1200);
+    assert_eq!(server.call("write_frame_bd", &[bdp_frame(), out.into()]), 0);
+    let token = server.host().bytes(out.tag)[2..].to_vec();
+    assert_eq!(server.call("on_frame_reserved_bd", &[bdp_frame()]), 0);
+    assert_eq!(server.call("should_send_frame_bd", &closing), 0);
+    assert_eq!(server.outputs(), &[false.into()]);
+
+    let mut client = endpoint(false);
+    assert_eq!(receive_frame(&mut client, &token), 0);
+    assert_eq!(client.call("plugin_control_80003", &[]), 0);
+    assert_eq!(client.outputs()[0], PluginVal::U64(137_500));
+    let mut server = endpoint(true);
+    set_path(&mut server, 12_000, Duration::from_millis(50));
+    assert_eq!(receive_frame(&mut server, &token), 0);
+    assert!(server.host().timer(0xbd).is_some());
+}
+
+#[test]
+fn oversized_tag_is_a_frame_encoding_error() {
+    let mut client = endpoint(false);
+    let inputs = [PluginVal::U64(100_000), Duration::from_millis(50).into(), client.host().now.into(), Duration::from_secs(3600).into()];
+    assert_eq!(client.call("plugin_control_80000", &inputs), 0);
+    let mut token = write_frame(&mut client);
+    assert_eq!(token.pop(), Some(0));
+    token.push(17);
+    token.extend_from_slice(&[0; 17]);
+
+    let mut server = endpoint(true);
+    let bytes = server.host_mut().new_bytes(&token, 0);
+    assert_eq!(server.call("parse_frame_bd", &[bytes.into()]), -0x07);
+}
+
+#[test]
+fn ramp_rtts_are_bounded() {
+    let mut server = endpoint(true);
+    set_path(&mut server, 100_000, Duration::from_millis(50));
+    assert_eq!(server.call("plugin_control_80000", &[]), 0);
+    let token = write_frame(&mut server);
+
+    let mut server = endpoint(true);
+    assert!(server.call("plugin_control_80004", &[PluginVal::U64(u32::MAX as u64)]) < 0);
+    assert!(server.call("plugin_control_80004", &[PluginVal::U64(65)]) < 0);
+    assert_eq!(server.call("plugin_control_80004", &[PluginVal::U64(64)]), 0);
+    set_path(&mut server, 12_000, Duration::from_millis(50));
+    assert_eq!(receive_frame(&mut server, &token), 0);
+    for step in 1..=64 {
+        let at = server.host().timer(0xbd).expect("still ramping").at;
+        assert_eq!(server.advance(at), vec![0]);
+        let expected = 12_000 + 88_000 * step / 64;
+        assert_eq!(server.host().recovery(RecoveryField::CongestionWindow), Some(PluginVal::Usize(expected)));
+    }
+    assert!(server.host().timers.is_empty());
+}
+
+#[test]
+fn losing_our_bdp_frame_does_not_stop_the_resume() {
+    let mut server = endpoint(true);
+    set_path(&mut server, 100_000, Duration::from_millis(50));
+    assert_eq!(server.call("plugin_control_80000", &[]), 0);
+    let token = write_frame(&mut server);
+
+    let mut server = endpoint(true);
+    set_path(&mut server, 12_000, Duration::from_millis(50));
+    assert_eq!(receive_frame(&mut server, &token), 0);
+    assert_eq!(server.call("notify_frame_bd", &[bdp_frame(), true.into()]), 0);
+    assert!(server.host().timer(0xbd).is_some());
+}
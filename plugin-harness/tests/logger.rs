@@ -0,0 +1,399 @@
+use plugin_harness::{quic::{self, ACKFrame, ConnectionCloseFrame, ConnectionField, Direction, EcnCount, Frame, HandshakeDoneFrame, IDList, MaxDataFrame, MaxStreamDataFrame, PacketType, QVal, RecoveryField, TransportParameterField}, Bytes, Duration, Plugin, PluginVal};
+use logger_events::{binary, qlog};
+use serde_json::Value;
+
+fn client() -> Plugin {
+    let mut p = Plugin::load("logger");
+    p.host_mut().set_connection(ConnectionField::IsServer, false);
+    assert_eq!(p.call("init", &[]), 0);
+    p
+}
+
+/// The JSON-SEQ records written to the trace.
+fn written(p: &Plugin) -> Vec<Value> {
+    let (_, content) = &p.host().files[0];
+    content.split(|b| *b == 0x1e)
+        .skip(1)
+        .map(|r| serde_json::from_slice(r).expect("JSON record"))
+        .collect()
+}
+
+/// The JSON-SEQ records of the trace, once the buffered ones are written.
+fn records(p: &mut Plugin) -> Vec<Value> {
+    assert_eq!(p.call("on_plugin_timeout_21", &[]), 0);
+    written(p)
+}
+
+fn max_data(maximum_data: u64) -> PluginVal {
+    PluginVal::QUIC(QVal::Frame(Frame::MaxData(MaxDataFrame { maximum_data })))
+}
+
+fn no_bytes() -> Bytes {
+    Bytes { tag: 0, max_read_len: 0, max_write_len: 0 }
+}
+
+fn ack(largest_acknowledged: u64, first_ack_range: u64) -> PluginVal {
+    let ack_ranges = no_bytes();
+    PluginVal::QUIC(QVal::Frame(Frame::ACK(ACKFrame {
+        largest_acknowledged,
+        ack_delay: 125,
+        ack_range_count: 0,
+        first_ack_range,
+        ack_ranges,
+        ecn_counts: None,
+    })))
+}
+
+#[test]
+fn init_writes_the_qlog_header() {
+    let mut p = client();
+    assert_eq!(p.call("post_process_frame_10", &[max_data(42)]), 0);
+    let records = records(&mut p);
+    assert_eq!(p.host().files[0].0, "log-client-1700000000.000000000.sqlog");
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["qlog_version"], "0.3");
+    assert_eq!(records[0]["qlog_format"], "JSON-SEQ");
+    assert_eq!(records[0]["trace"]["vantage_point"]["type"], "client");
+    assert_eq!(records[0]["trace"]["common_fields"]["reference_time"], 1_700_000_000_000.0);
+}
+
+/// Expose the connection IDs in `direction` with increasing sequence numbers
+/// from `min`.
+fn set_cids(p: &mut Plugin, direction: Direction, min: u64, cids: &[&[u8]]) {
+    p.host_mut().set_connection(ConnectionField::ConnectionID(direction, IDList::MinID), min);
+    p.host_mut().set_connection(ConnectionField::ConnectionID(direction, IDList::MaxID), min + cids.len() as u64 - 1);
+    for (seq, cid) in (min..).zip(cids) {
+        let b = p.host_mut().new_bytes(cid, 0);
+        p.host_mut().set_connection(ConnectionField::ConnectionID(direction, IDList::Elem(seq)), b);
+    }
+}
+
+#[test]
+fn header_identifies_the_connection() {
+    let mut client = Plugin::load("logger");
+    client.host_mut().set_connection(ConnectionField::IsServer, false);
+    client.host_mut().set_connection(ConnectionField::Version, 1u32);
+    set_cids(&mut client, Direction::Source, 0, &[&[0xc1, 0x1e]]);
+    set_cids(&mut client, Direction::Destination, 0, &[&[0x5e, 0x7e]]);
+    assert_eq!(client.call("init", &[]), 0);
+    let mut server = Plugin::load("logger");
+    server.host_mut().set_connection(ConnectionField::IsServer, true);
+    set_cids(&mut server, Direction::Source, 0, &[&[0x5e, 0x7e]]);
+    set_cids(&mut server, Direction::Destination, 0, &[&[0xc1, 0x1e]]);
+    assert_eq!(server.call("init", &[]), 0);
+
+    // The peer transport parameters and new connection IDs are known once the
+    // handshake completes.
+    let exponent = ConnectionField::TransportParameter(quic::Host::Remote, TransportParameterField::AckDelayExponent);
+    client.host_mut().set_connection(exponent, 10u64);
+    set_cids(&mut client, Direction::Source, 0, &[&[0xc1, 0x1e], &[0xc1, 0x1f]]);
+    set_cids(&mut client, Direction::Destination, 0, &[&[0x5e, 0x7e]]);
+    // The bytes of the connection IDs are read once.
+    set_cids(&mut server, Direction::Source, 0, &[&[0x5e, 0x7e]]);
+    set_cids(&mut server, Direction::Destination, 0, &[&[0xc1, 0x1e]]);
+    let handshake_done = PluginVal::QUIC(QVal::Frame(Frame::HandshakeDone(HandshakeDoneFrame)));
+    assert_eq!(client.call("post_process_frame_1e", &[handshake_done]), 0);
+    assert_eq!(server.call("post_write_frame_1e", &[handshake_done]), 0);
+
+    let client_records = records(&mut client);
+    let server_records = records(&mut server);
+    assert_eq!(client_records[0]["trace"]["common_fields"]["group_id"], "c11e");
+    assert_eq!(server_records[0]["trace"]["common_fields"]["group_id"], "c11e");
+    let connection = &client_records[0]["trace"]["connection"];
+    assert_eq!(connection["version"], "00000001");
+    assert_eq!(connection["peer_cids"], serde_json::json!([{ "sequence_number": 0, "connection_id": "5e7e" }]));
+    assert_eq!(connection["alpn"], Value::Null);
+    // Written after the handshake, the header holds what it changed.
+    assert_eq!(connection["local_cids"][1]["connection_id"], "c11f");
+    assert_eq!(connection["peer_parameters"]["ack_delay_exponent"], 10);
+    assert_eq!(client_records[2]["name"], "pluginop:connection_updated");
+    assert_eq!(client_records[2]["data"], *connection);
+    // Nothing changed for the server.
+    assert_eq!(server_records.len(), 2);
+}
+
+#[test]
+fn processed_ack_logs_frame_and_metrics() {
+    let mut p = client();
+    p.host_mut().set_recovery(RecoveryField::SmoothedRtt, Duration::from_millis(30));
+    p.host_mut().set_recovery(RecoveryField::CongestionWindow, 14720usize);
+    p.host_mut().now = p.host().now + Duration::from_millis(5);
+    assert_eq!(p.call("post_process_frame_2", &[ack(10, 3)]), 0);
+    // Nothing changed, no new metrics.
+    assert_eq!(p.call("post_process_frame_2", &[ack(11, 4)]), 0);
+
+    let records = records(&mut p);
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[1]["name"], "transport:frames_processed");
+    assert_eq!(records[1]["time"], 5.0);
+    let frame = &records[1]["data"]["frames"][0];
+    assert_eq!(frame["frame_type"], "ack");
+    // Default ack_delay_exponent of 3.
+    assert_eq!(frame["ack_delay"], 1.0);
+    assert_eq!(frame["acked_ranges"], serde_json::json!([[7, 10]]));
+    assert_eq!(records[2]["name"], "recovery:metrics_updated");
+    assert_eq!(records[2]["data"], serde_json::json!({ "smoothed_rtt": 30.0, "congestion_window": 14720 }));
+    assert_eq!(records[3]["name"], "transport:frames_processed");
+}
+
+#[test]
+fn all_rfc9000_frames_are_hooked() {
+    let p = client();
+    for ty in 0..=0x1e {
+        for po in ["post_process_frame", "post_write_frame", "post_notify_frame"] {
+            assert!(p.exports(&format!("{po}_{ty:x}")), "{po} of frame 0x{ty:x}");
+        }
+    }
+}
+
+#[test]
+fn processed_frames_log_their_fields() {
+    let mut p = client();
+    let msd = Frame::MaxStreamData(MaxStreamDataFrame { stream_id: 4, maximum_stream_data: 65536 });
+    assert_eq!(p.call("post_process_frame_11", &[PluginVal::QUIC(QVal::Frame(msd))]), 0);
+    let close = Frame::ConnectionClose(ConnectionCloseFrame { error_code: 0xa, frame_type: Some(0x6), reason_phrase_length: 0, reason_phrase: no_bytes() });
+    assert_eq!(p.call("post_process_frame_1c", &[PluginVal::QUIC(QVal::Frame(close))]), 0);
+    assert!(p.call("post_process_frame_10", &[PluginVal::U64(0)]) < 0);
+    assert!(p.call("post_notify_frame_10", &[max_data(42)]) < 0);
+
+    let records = records(&mut p);
+    assert_eq!(records.len(), 5);
+    assert_eq!(records[1]["data"]["frames"][0], serde_json::json!({ "frame_type": "max_stream_data", "stream_id": 4, "maximum": 65536 }));
+    let close = &records[2]["data"]["frames"][0];
+    assert_eq!(close["error_space"], "transport");
+    assert_eq!(close["raw_error_code"], 0xa);
+    assert_eq!(close["trigger_frame_type"], 0x6);
+    assert_eq!(records[3]["name"], "pluginop:unexpected_input");
+    assert_eq!(records[3]["data"], serde_json::json!({ "operation": "post_process_frame_10", "index": 0, "found": "u64" }));
+    assert_eq!(records[4]["data"], serde_json::json!({ "operation": "post_notify_frame_10", "index": 1, "found": "missing" }));
+}
+
+#[test]
+fn ack_frames_log_all_their_ranges() {
+    let mut p = client();
+    // Ranges [20, 22], then 2 missing packets before [15, 17] and 1 before
+    // [12, 13].
+    let ack_ranges = p.host_mut().new_bytes(&[0x01, 0x02, 0x00, 0x01], 0);
+    let ack = Frame::ACK(ACKFrame {
+        largest_acknowledged: 22,
+        ack_delay: 0,
+        ack_range_count: 2,
+        first_ack_range: 2,
+        ack_ranges,
+        ecn_counts: Some(EcnCount { ect0_count: 5, ect1_count: 0, ectce_count: 1 }),
+    });
+    assert_eq!(p.call("post_process_frame_3", &[PluginVal::QUIC(QVal::Frame(ack))]), 0);
+    // A malformed range is not logged.
+    let ack_ranges = p.host_mut().new_bytes(&[0x01, 0x40], 0);
+    let ack = Frame::ACK(ACKFrame { largest_acknowledged: 22, ack_delay: 0, ack_range_count: 1, first_ack_range: 2, ack_ranges, ecn_counts: None });
+    assert_eq!(p.call("post_process_frame_2", &[PluginVal::QUIC(QVal::Frame(ack))]), 0);
+
+    let records = records(&mut p);
+    let frame = &records[1]["data"]["frames"][0];
+    assert_eq!(frame["acked_ranges"], serde_json::json!([[20, 22], [15, 17], [12, 13]]));
+    assert_eq!((frame["ect0"].as_u64(), frame["ect1"].as_u64(), frame["ce"].as_u64()), (Some(5), Some(0), Some(1)));
+    let frame = &records[2]["data"]["frames"][0];
+    assert_eq!(frame["acked_ranges"], serde_json::json!([[20, 22]]));
+    assert_eq!(frame.get("ect0"), None);
+}
+
+#[test]
+fn sent_frames_log_their_outcome() {
+    let mut p = client();
+    let md = PluginVal::QUIC(QVal::Frame(Frame::MaxData(MaxDataFrame { maximum_data: 1 << 20 })));
+    let out = p.host_mut().new_bytes(&[], 100);
+    assert_eq!(p.call("post_write_frame_10", &[md, out.into()]), 0);
+    assert_eq!(p.call("post_notify_frame_10", &[md, true.into()]), 0);
+    assert_eq!(p.call("post_notify_frame_10", &[md, false.into()]), 0);
+
+    let names: Vec<_> = records(&mut p)[1..].iter().map(|r| r["name"].clone()).collect();
+    assert_eq!(names, ["pluginop:frames_sent", "recovery:marked_for_retransmit", "pluginop:frames_acked"]);
+    assert_eq!(written(&p)[2]["data"]["frames"][0]["maximum"], 1 << 20);
+}
+
+#[test]
+fn packet_events_carry_their_epoch() {
+    let packet = logger_events::Packet {
+        packet_type: PacketType::Short,
+        epoch: logger_events::epoch(PacketType::Short),
+        packet_number: Some(7),
+        size: 1200,
+        frames: vec![0x10, 0x01],
+    };
+    let record = logger_events::Record { time: Duration::from_millis(2), event: logger_events::Event::Packet { kind: logger_events::PacketEvent::Sent, packet } };
+    let header = logger_events::Header { is_server: false, reference_time: Duration::ZERO, connection: Default::default() };
+    let trace = [binary::header(&header), binary::record(&record)].concat();
+    let (_, mut decoder) = binary::Decoder::new(&trace).unwrap();
+    assert_eq!(decoder.next().unwrap().unwrap(), record);
+    let event = qlog::event(&record);
+    assert_eq!(event["name"], "transport:packet_sent");
+    assert_eq!(event["data"], serde_json::json!({
+        "header": { "packet_type": "1RTT", "packet_number": 7, "epoch": "application_data" },
+        "raw": { "length": 1200 },
+        "frames": [{ "frame_type": "max_data" }, { "frame_type": "ping" }],
+    }));
+}
+
+#[test]
+fn metrics_are_sampled_periodically() {
+    let mut p = client();
+    let timer = p.host().timer(0x20).expect("sampling timer");
+    assert_eq!(timer.timer_id, 0x20);
+    p.host_mut().set_recovery(RecoveryField::CongestionWindow, 12000usize);
+    p.host_mut().set_recovery(RecoveryField::MinRtt, Duration::from_millis(20));
+    p.host_mut().set_connection(ConnectionField::MaxTxData, 1_000_000u64);
+    assert_eq!(p.advance(timer.at), vec![0]);
+    let next = p.host().timer(0x20).expect("sampling timer rearmed");
+    assert_eq!(next.at - timer.at, Duration::from_millis(100));
+    p.host_mut().set_recovery(RecoveryField::CongestionWindow, 24000usize);
+    assert_eq!(p.advance(next.at), vec![0]);
+
+    let records = records(&mut p);
+    assert_eq!(records.len(), 3);
+    let first = &records[1];
+    assert_eq!(first["name"], "recovery:metrics_updated");
+    assert_eq!(first["time"], 100.0);
+    assert_eq!(first["data"]["congestion_window"], 12000);
+    assert_eq!(first["data"]["min_rtt"], 20.0);
+    assert_eq!(first["data"]["max_tx_data"], 1_000_000);
+    // Every sample holds all the metrics.
+    assert_eq!(first["data"].as_object().unwrap().len(), 8);
+    assert_eq!(records[2]["data"]["congestion_window"], 24000);
+    assert_eq!(records[2]["data"]["min_rtt"], 20.0);
+}
+
+#[test]
+fn events_are_written_by_batches() {
+    let mut p = client();
+    let timer = p.host().timer(0x21).expect("flush timer");
+    assert_eq!(timer.at - p.host().now, Duration::from_secs(1));
+    let msd = Frame::MaxStreamData(MaxStreamDataFrame { stream_id: 4, maximum_stream_data: 65536 });
+    assert_eq!(p.call("post_process_frame_11", &[PluginVal::QUIC(QVal::Frame(msd))]), 0);
+    // Files are created on the first write.
+    assert!(p.host().files.is_empty());
+
+    // Closing the connection writes the buffered events.
+    let close = Frame::ConnectionClose(ConnectionCloseFrame { error_code: 0, frame_type: None, reason_phrase_length: 0, reason_phrase: no_bytes() });
+    assert_eq!(p.call("post_write_frame_1d", &[PluginVal::QUIC(QVal::Frame(close))]), 0);
+    let records = written(&p);
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[2]["data"]["frames"][0]["error_space"], "application");
+}
+
+#[test]
+fn prefix_and_format_are_configurable() {
+    let mut p = client();
+    let prefix = b"traces/q-";
+    let mut words = vec![PluginVal::U64(prefix.len() as u64)];
+    for chunk in prefix.chunks(8) {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        words.push(PluginVal::U64(u64::from_be_bytes(word)));
+    }
+    assert_eq!(p.call("plugin_control_80020", &words), 0);
+    assert_eq!(p.call("plugin_control_80022", &[PluginVal::U64(1)]), 0);
+    assert!(p.call("plugin_control_80022", &[PluginVal::U64(7)]) < 0);
+    assert_eq!(p.call("post_process_frame_10", &[max_data(42)]), 0);
+    assert_eq!(p.call("on_plugin_timeout_21", &[]), 0);
+
+    let (name, content) = &p.host().files[0];
+    assert_eq!(name, "traces/q-client-1700000000.000000000.log");
+    let text = String::from_utf8(content.clone()).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert!(lines[0].starts_with("# {"));
+    assert!(lines[1].starts_with("0.0 transport:frames_processed {"));
+}
+
+#[test]
+fn binary_traces_decode_to_qlog() {
+    let mut p = client();
+    assert_eq!(p.call("plugin_control_80022", &[PluginVal::U64(2)]), 0);
+    p.host_mut().set_recovery(RecoveryField::SmoothedRtt, Duration::from_millis(30));
+    p.host_mut().now = p.host().now + Duration::from_millis(5);
+    assert_eq!(p.call("post_process_frame_2", &[ack(10, 3)]), 0);
+    assert_eq!(p.call("post_write_frame_10", &[max_data(42)]), 0);
+    assert_eq!(p.call("on_plugin_timeout_21", &[]), 0);
+
+    let (name, content) = &p.host().files[0];
+    assert_eq!(name, "log-client-1700000000.000000000.blog");
+    let (header, decoder) = binary::Decoder::new(content).unwrap();
+    assert_eq!(qlog::header(&header)["trace"]["common_fields"]["reference_time"], 1_700_000_000_000.0);
+    let events: Vec<Value> = decoder.map(|r| qlog::event(&r.unwrap())).collect();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0]["name"], "transport:frames_processed");
+    assert_eq!(events[0]["time"], 5.0);
+    assert_eq!(events[0]["data"]["frames"][0]["acked_ranges"], serde_json::json!([[7, 10]]));
+    assert_eq!(events[1]["data"], serde_json::json!({ "smoothed_rtt": 30.0 }));
+    assert_eq!(events[2]["name"], "pluginop:frames_sent");
+    assert_eq!(events[2]["data"]["frames"][0]["maximum"], 42);
+
+    // Much more compact than JSON-SEQ.
+    let mut json = client();
+    assert_eq!(json.call("post_process_frame_2", &[ack(10, 3)]), 0);
+    assert_eq!(json.call("post_write_frame_10", &[max_data(42)]), 0);
+    assert_eq!(json.call("on_plugin_timeout_21", &[]), 0);
+    assert!(content.len() * 2 < json.host().files[0].1.len());
+}
+
+#[test]
+fn categories_and_logging_can_be_toggled() {
+    let mut p = client();
+    // Only the recovery metrics.
+    assert_eq!(p.call("plugin_control_80021", &[PluginVal::U64(0x2)]), 0);
+    assert_eq!(p.call("post_process_frame_10", &[max_data(1)]), 0);
+    assert_eq!(p.call("on_plugin_timeout_21", &[]), 0);
+    assert!(p.host().files.is_empty());
+
+    assert_eq!(p.call("plugin_control_80021", &[PluginVal::U64(0xf)]), 0);
+    assert_eq!(p.call("plugin_control_80023", &[false.into()]), 0);
+    assert_eq!(p.call("post_process_frame_10", &[max_data(2)]), 0);
+    p.host_mut().now = p.host().now + Duration::from_secs(2);
+    assert_eq!(p.call("plugin_control_80023", &[true.into()]), 0);
+    assert_eq!(p.call("post_process_frame_10", &[max_data(3)]), 0);
+    assert_eq!(p.call("on_plugin_timeout_21", &[]), 0);
+
+    // Logging again goes to new files, with the same reference time.
+    let files = &p.host().files;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].0, "log-client-1700000002.000000000.sqlog");
+    let first: Vec<Value> = files[0].1.split(|b| *b == 0x1e).skip(1).map(|r| serde_json::from_slice(r).unwrap()).collect();
+    assert_eq!(first.len(), 2);
+    assert_eq!(first[0]["trace"]["common_fields"]["reference_time"], 1_700_000_000_000.0);
+    assert_eq!(first[1]["data"]["frames"][0]["maximum"], 3);
+    assert_eq!(first[1]["time"], 2000.0);
+}
+
+#[test]
+fn full_output_rotates_then_drops() {
+    let mut p = client();
+    // Files of at most 400 bytes, 2 of them.
+    assert_eq!(p.call("plugin_control_80024", &[PluginVal::U64(400), PluginVal::U64(2)]), 0);
+    for i in 0..20 {
+        assert_eq!(p.call("post_process_frame_10", &[max_data(i)]), 0);
+        assert_eq!(p.call("on_plugin_timeout_21", &[]), 0);
+    }
+
+    let files = &p.host().files;
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[1].0, "log-client-1700000000.000000000.1.sqlog");
+    let records: Vec<Vec<Value>> = files.iter()
+        .map(|(_, c)| c.split(|b| *b == 0x1e).skip(1).map(|r| serde_json::from_slice(r).unwrap()).collect())
+        .collect();
+    // Both files start with the header.
+    assert!(records.iter().all(|r| r[0]["qlog_version"] == "0.3"));
+    let logged = records.iter().map(|r| r.len() - 1).sum::<usize>();
+    let dropped: u64 = records[1].iter().filter(|r| r["name"] == "pluginop:events_dropped").map(|r| r["data"]["count"].as_u64().unwrap()).sum();
+    let reports = records[1].iter().filter(|r| r["name"] == "pluginop:events_dropped").count();
+    assert!(dropped > 0);
+    assert_eq!(logged - reports + dropped as usize, 20);
+}
+
+#[test]
+fn malformed_ack_delays_saturate() {
+    let ack_ranges = Bytes { tag: 0, max_read_len: 0, max_write_len: 0 };
+    let ack = |ack_delay| Frame::ACK(ACKFrame { largest_acknowledged: 1, ack_delay, ack_range_count: 0, first_ack_range: 0, ack_ranges, ecn_counts: None });
+    let max = qlog::ms(Duration::from_micros(u64::MAX));
+    assert_eq!(qlog::frame(&ack(1), 200, &[])["ack_delay"].as_f64(), Some(max));
+    assert_eq!(qlog::frame(&ack(u64::MAX >> 2), 3, &[])["ack_delay"].as_f64(), Some(max));
+    assert_eq!(qlog::frame(&ack(125), 3, &[])["ack_delay"].as_f64(), Some(1.0));
+}
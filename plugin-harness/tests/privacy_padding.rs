@@ -0,0 +1,288 @@
+use plugin_harness::{quic::{ConnectionField, Frame, PacketType, PaddingFrame, PingFrame, QVal, RecoveryField}, Duration, Plugin, PluginVal, UnixInstant};
+
+fn should_send_inputs(p: &Plugin, left: usize) -> Vec<PluginVal> {
+    vec![PluginVal::QUIC(QVal::PacketType(PacketType::Short)), PluginVal::U64(0), false.into(), left.into(), p.host().now.into()]
+}
+
+fn established() -> Plugin {
+    let mut p = Plugin::load("privacy-padding");
+    assert_eq!(p.call("init", &[]), 0);
+    p.host_mut().set_connection(ConnectionField::IsEstablished, true);
+    p
+}
+
+/// The outputs of the send gate for a 1-RTT packet.
+fn send_gate(p: &mut Plugin) -> Vec<PluginVal> {
+    let inputs = [PluginVal::QUIC(QVal::PacketType(PacketType::Short)), p.host().now.into()];
+    assert_eq!(p.call("send_gate", &inputs), 0);
+    p.outputs().to_vec()
+}
+
+#[test]
+fn send_gate_holds_packets_during_the_pause() {
+    let mut p = established();
+    // The pauses have no effect on hosts not calling the gate.
+    assert!(p.host().printed.iter().any(|m| m.contains("send_gate")));
+    assert_eq!(send_gate(&mut p), [true.into()]);
+    let inputs = should_send_inputs(&p, 100);
+    assert_eq!(p.call("should_send_frame_0", &inputs), 0);
+    assert_eq!(p.outputs(), &[true.into()]);
+    let at = match send_gate(&mut p)[..] {
+        [PluginVal::Bool(false), PluginVal::UNIXInstant(at)] => at,
+        ref o => panic!("unexpected outputs {o:?}"),
+    };
+    assert!(at - p.host().now < Duration::from_millis(10));
+
+    // Only the 1-RTT packets are held.
+    let inputs = [PluginVal::QUIC(QVal::PacketType(PacketType::Handshake)), p.host().now.into()];
+    assert_eq!(p.call("send_gate", &inputs), 0);
+    assert_eq!(p.outputs(), &[true.into()]);
+
+    p.advance(at);
+    assert_eq!(send_gate(&mut p), [true.into()]);
+}
+
+#[test]
+fn padding_fills_the_packet() {
+    let mut p = established();
+    assert_eq!(p.call("prepare_frame_0", &[PluginVal::U64(0), 17usize.into()]), 0);
+    let frame = p.outputs()[0];
+    assert_eq!(frame, PluginVal::QUIC(QVal::Frame(Frame::Padding(PaddingFrame { length: 17 }))));
+    let out = p.host_mut().new_bytes(&[], 17);
+    assert_eq!(p.call("write_frame_0", &[frame, out.into()]), 0);
+    assert_eq!(p.host().bytes(out.tag), &[0; 17]);
+}
+
+#[test]
+fn plugin_control_disables_padding() {
+    let mut p = established();
+    assert_eq!(p.call("plugin_control_80001", &[false.into()]), 0);
+    let inputs = should_send_inputs(&p, 100);
+    assert_eq!(p.call("should_send_frame_0", &inputs), 0);
+    assert_eq!(p.outputs(), &[false.into()]);
+    assert_eq!(send_gate(&mut p), [true.into()]);
+}
+
+/// The pause drawn before sending the next packet.
+fn next_pause(p: &mut Plugin) -> Duration {
+    let inputs = should_send_inputs(p, 100);
+    assert_eq!(p.call("should_send_frame_0", &inputs), 0);
+    let [PluginVal::Bool(false), PluginVal::UNIXInstant(at)] = send_gate(p)[..] else { panic!("not paused") };
+    let pause = at - p.host().now;
+    p.advance(at);
+    pause
+}
+
+fn load_samples(p: &mut Plugin, samples: &str) {
+    // The file does not fit in a single plugin control.
+    for chunk in samples.as_bytes().chunks(40) {
+        let mut inputs = vec![PluginVal::U64(chunk.len() as u64)];
+        for word in chunk.chunks(8) {
+            let mut w = [0; 8];
+            w[..word.len()].copy_from_slice(word);
+            inputs.push(PluginVal::U64(u64::from_be_bytes(w)));
+        }
+        assert_eq!(p.call("plugin_control_80011", &inputs), 0);
+    }
+}
+
+#[test]
+fn constant_rate_profile_spaces_full_packets() {
+    let mut p = established();
+    p.host_mut().set_connection(ConnectionField::MaxSendUdpPayloadLength, 1250u64);
+    assert!(p.call("plugin_control_80010", &[PluginVal::U64(1), PluginVal::U64(0)]) < 0);
+    assert_eq!(p.call("plugin_control_80010", &[PluginVal::U64(1), PluginVal::U64(125_000)]), 0);
+    assert_eq!(next_pause(&mut p), Duration::from_millis(10));
+    assert_eq!(next_pause(&mut p), Duration::from_millis(10));
+}
+
+#[test]
+fn distribution_and_trace_profiles_use_the_loaded_samples() {
+    let mut p = established();
+    // Nothing loaded yet.
+    assert!(p.call("plugin_control_80010", &[PluginVal::U64(3)]) < 0);
+    let trace = "# gaps of a recorded page load, in microseconds\n150\n\n2000\n  35000\n400\n";
+    load_samples(&mut p, trace);
+    assert_eq!(p.call("plugin_control_80010", &[PluginVal::U64(3)]), 0);
+    let pauses: Vec<_> = (0..5).map(|_| next_pause(&mut p)).collect();
+    let expected = [150, 2000, 35000, 400, 150].map(Duration::from_micros);
+    assert_eq!(pauses, expected);
+
+    load_samples(&mut p, "300\n700\n");
+    assert_eq!(p.call("plugin_control_80010", &[PluginVal::U64(2)]), 0);
+    for _ in 0..20 {
+        let pause = next_pause(&mut p);
+        assert!(pause == Duration::from_micros(300) || pause == Duration::from_micros(700), "{pause:?}");
+    }
+
+    // The samples are consumed, even if malformed.
+    assert!(p.call("plugin_control_80010", &[PluginVal::U64(2)]) < 0);
+    load_samples(&mut p, "12\nnot a gap\n");
+    assert!(p.call("plugin_control_80010", &[PluginVal::U64(2)]) < 0);
+    assert!(p.call("plugin_control_80010", &[PluginVal::U64(2)]) < 0);
+}
+
+/// An established connection sending chaff after `interval` idle, within
+/// `budget` bytes per second.
+fn covered(interval: Duration, budget: u64) -> Plugin {
+    let mut p = established();
+    p.host_mut().set_recovery(RecoveryField::CongestionWindow, 100_000usize);
+    p.host_mut().set_recovery(RecoveryField::BytesInFlight, 0usize);
+    let inputs = [PluginVal::U64(interval.as_micros() as u64), PluginVal::U64(budget)];
+    assert_eq!(p.call("plugin_control_80012", &inputs), 0);
+    p
+}
+
+/// Whether the next 1-RTT packet is a chaff one, building it if so.
+fn chaff(p: &mut Plugin) -> bool {
+    let inputs = should_send_inputs(p, 1200);
+    assert_eq!(p.call("should_send_frame_1", &inputs), 0);
+    let chaff = p.outputs() == [true.into()];
+    if chaff {
+        assert_eq!(p.call("prepare_frame_1", &[]), 0);
+        assert_eq!(p.outputs(), &[PluginVal::QUIC(QVal::Frame(Frame::Ping(PingFrame)))]);
+        assert_eq!(p.call("should_send_frame_0", &inputs), 0);
+        assert_eq!(p.outputs(), &[true.into()]);
+    }
+    chaff
+}
+
+fn advance_by(p: &mut Plugin, d: Duration) {
+    let at: UnixInstant = p.host().now + d;
+    assert!(p.advance(at).iter().all(|c| *c == 0));
+}
+
+#[test]
+fn chaff_is_sent_when_idle() {
+    let interval = Duration::from_millis(20);
+    let mut p = covered(interval, 1_000_000);
+    assert!(!chaff(&mut p));
+    advance_by(&mut p, interval);
+    assert!(chaff(&mut p));
+    // Only once.
+    assert!(!chaff(&mut p));
+    advance_by(&mut p, interval);
+    assert!(chaff(&mut p));
+
+    // Not while the application sends.
+    advance_by(&mut p, interval / 2);
+    let inputs = should_send_inputs(&p, 1200);
+    assert_eq!(p.call("should_send_frame_0", &inputs), 0);
+    advance_by(&mut p, interval / 2);
+    assert!(!chaff(&mut p));
+    advance_by(&mut p, interval / 2);
+    assert!(chaff(&mut p));
+
+    // Disabled.
+    assert_eq!(p.call("plugin_control_80012", &[PluginVal::U64(0), PluginVal::U64(0)]), 0);
+    advance_by(&mut p, interval * 5);
+    assert!(!chaff(&mut p));
+    assert!(p.host().timer(8).is_none());
+}
+
+#[test]
+fn chaff_respects_the_budget_and_the_congestion_window() {
+    let interval = Duration::from_millis(20);
+    // A single packet per second.
+    let mut p = covered(interval, 1200);
+    let mut sent = 0;
+    for _ in 0..100 {
+        advance_by(&mut p, interval);
+        sent += chaff(&mut p) as u32;
+    }
+    assert_eq!(sent, 2);
+
+    // A packet every two seconds.
+    let mut p = covered(interval, 600);
+    let mut sent = 0;
+    for _ in 0..200 {
+        advance_by(&mut p, interval);
+        sent += chaff(&mut p) as u32;
+    }
+    assert_eq!(sent, 2);
+
+    let mut p = covered(interval, 1_000_000);
+    p.host_mut().set_recovery(RecoveryField::BytesInFlight, 99_000usize);
+    advance_by(&mut p, interval);
+    assert!(!chaff(&mut p));
+    p.host_mut().set_recovery(RecoveryField::BytesInFlight, 0usize);
+    advance_by(&mut p, interval);
+    assert!(chaff(&mut p));
+}
+
+#[test]
+fn statistics_account_for_the_overhead_and_the_delay() {
+    let interval = Duration::from_millis(20);
+    let mut p = covered(interval, 1_000_000);
+    advance_by(&mut p, interval);
+    assert!(chaff(&mut p));
+    let frame = PluginVal::QUIC(QVal::Frame(Frame::Padding(PaddingFrame { length: 1100 })));
+    let out = p.host_mut().new_bytes(&[], 1100);
+    assert_eq!(p.call("write_frame_0", &[frame, out.into()]), 0);
+
+    // The pause only counts once a packet is held.
+    let [PluginVal::Bool(false), PluginVal::UNIXInstant(at)] = send_gate(&mut p)[..] else { panic!("not paused") };
+    let delay = at - p.host().now;
+    p.advance(at);
+    assert_eq!(send_gate(&mut p), [true.into()]);
+
+    assert_eq!(p.call("plugin_control_80013", &[]), 0);
+    assert_eq!(p.outputs(), &[PluginVal::U64(1100), PluginVal::U64(1), PluginVal::U64(1), PluginVal::Duration(delay)]);
+
+    // Written once the connection closes.
+    let name = b"pp-stats.csv";
+    let mut w = [0; 16];
+    w[..name.len()].copy_from_slice(name);
+    let inputs = [PluginVal::U64(name.len() as u64), PluginVal::U64(u64::from_be_bytes(w[..8].try_into().unwrap())), PluginVal::U64(u64::from_be_bytes(w[8..].try_into().unwrap()))];
+    assert_eq!(p.call("plugin_control_80014", &inputs), 0);
+    assert!(p.host().files.is_empty());
+    assert_eq!(p.call("post_write_frame_1c", &[]), 0);
+    assert_eq!(p.call("post_process_frame_1d", &[]), 0);
+    assert_eq!(p.host().files.len(), 1);
+    let (file, content) = &p.host().files[0];
+    assert_eq!(file, "pp-stats.csv");
+    let expected = format!("padded_bytes,chaff_packets,pauses,delay_us\n1100,1,1,{}\n", delay.as_micros());
+    assert_eq!(String::from_utf8_lossy(content), expected);
+}
+
+/// The padding completing a 1-RTT packet having `left` bytes of room, if any.
+fn padding_len(p: &mut Plugin, left: usize) -> Option<u64> {
+    let inputs = should_send_inputs(p, left);
+    assert_eq!(p.call("should_send_frame_0", &inputs), 0);
+    if p.outputs() != [true.into()] {
+        return None;
+    }
+    assert_eq!(p.call("prepare_frame_0", &[PluginVal::U64(0), left.into()]), 0);
+    match p.outputs() {
+        [PluginVal::QUIC(QVal::Frame(Frame::Padding(PaddingFrame { length })))] => Some(*length),
+        o => panic!("unexpected outputs {o:?}"),
+    }
+}
+
+#[test]
+fn packets_are_padded_to_the_configured_sizes() {
+    let mut p = established();
+    p.host_mut().set_connection(ConnectionField::MaxSendUdpPayloadLength, 1200u64);
+    assert_eq!(padding_len(&mut p, 1000), Some(1000));
+
+    let sizes = [1024, 256, 512].map(PluginVal::U64);
+    assert!(p.call("plugin_control_80015", &[PluginVal::U64(1)]) < 0);
+    assert!(p.call("plugin_control_80015", &[PluginVal::U64(1), PluginVal::U64(0)]) < 0);
+    assert!(p.call("plugin_control_80015", &[&[PluginVal::U64(3)], &sizes[..]].concat()) < 0);
+    assert_eq!(p.call("plugin_control_80015", &[&[PluginVal::U64(1)], &sizes[..]].concat()), 0);
+    assert_eq!(padding_len(&mut p, 1000), Some(56));
+    assert_eq!(padding_len(&mut p, 700), Some(12));
+    assert_eq!(padding_len(&mut p, 688), None);
+    // Larger than all the buckets.
+    assert_eq!(padding_len(&mut p, 150), Some(150));
+
+    let sizes = [300, 900].map(PluginVal::U64);
+    assert_eq!(p.call("plugin_control_80015", &[&[PluginVal::U64(2)], &sizes[..]].concat()), 0);
+    let lens: Vec<_> = (0..20).map(|_| padding_len(&mut p, 1000)).collect();
+    assert!(lens.iter().all(|l| *l == Some(100) || *l == Some(700)), "{lens:?}");
+    assert!(lens.contains(&Some(100)) && lens.contains(&Some(700)));
+    assert_eq!(padding_len(&mut p, 700), Some(400));
+
+    assert_eq!(p.call("plugin_control_80015", &[PluginVal::U64(0)]), 0);
+    assert_eq!(padding_len(&mut p, 700), Some(700));
+}
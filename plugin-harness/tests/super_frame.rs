@@ -0,0 +1,67 @@
+use plugin_harness::{quic::{ExtensionFrame, Frame, PacketType, QVal, Registration}, Plugin, PluginVal};
+
+fn extension(tag: u64) -> PluginVal {
+    PluginVal::QUIC(QVal::Frame(Frame::Extension(ExtensionFrame { frame_type: 0x42, tag })))
+}
+
+#[test]
+fn init_registers_frame_and_tp() {
+    let mut p = Plugin::load("super-frame");
+    assert_eq!(p.call("init", &[]), 0);
+    let regs = &p.host().registrations;
+    assert!(matches!(regs[0], Registration::TransportParameter(0xaaaaaaaa)));
+    assert!(matches!(regs[1], Registration::Frame(_)));
+}
+
+#[test]
+fn parse_frame_stores_the_byte() {
+    let mut p = Plugin::load("super-frame");
+    let bytes = p.host_mut().new_bytes(&[7, 0xff], 0);
+    assert_eq!(p.call("parse_frame_42", &[bytes.into()]), 0);
+    assert_eq!(p.outputs(), &[extension(0)]);
+    assert_eq!(p.host().unread(bytes.tag), 1);
+
+    let out = p.host_mut().new_bytes(&[], 1000);
+    assert_eq!(p.call("write_frame_42", &[extension(0), out.into()]), 0);
+    // 0x42 takes two bytes as a varint.
+    assert_eq!(p.host().bytes(out.tag), &[0x40, 0x42, 7]);
+    assert_eq!(p.outputs(), &[PluginVal::Usize(3)]);
+}
+
+#[test]
+fn should_send_once_in_flight() {
+    let mut p = Plugin::load("super-frame");
+    let inputs = [PluginVal::QUIC(QVal::PacketType(PacketType::Short)), PluginVal::U64(0), false.into(), 1200usize.into()];
+    assert_eq!(p.call("should_send_frame_42", &inputs), 0);
+    assert_eq!(p.outputs(), &[true.into()]);
+
+    assert_eq!(p.call("prepare_frame_42", &[]), 0);
+    let frame = p.outputs()[0];
+    assert_eq!(p.call("on_frame_reserved_42", &[frame]), 0);
+    assert_eq!(p.call("should_send_frame_42", &inputs), 0);
+    assert_eq!(p.outputs(), &[false.into()]);
+
+    assert_eq!(p.call("notify_frame_42", &[frame, true.into()]), 0);
+    assert_eq!(p.call("should_send_frame_42", &inputs), 0);
+    assert_eq!(p.outputs(), &[true.into()]);
+}
+
+#[test]
+fn should_send_without_the_left_bytes() {
+    let mut p = Plugin::load("super-frame");
+    let inputs = [PluginVal::QUIC(QVal::PacketType(PacketType::Short)), PluginVal::U64(0), false.into()];
+    assert_eq!(p.call("should_send_frame_42", &inputs), 0);
+    assert_eq!(p.outputs(), &[true.into()]);
+}
+
+#[test]
+fn process_frame_toggles_privacy_padding() {
+    let mut p = Plugin::load("super-frame");
+    p.host_mut().on_poctl(0x80001, vec![]);
+    let bytes = p.host_mut().new_bytes(&[1], 0);
+    assert_eq!(p.call("parse_frame_42", &[bytes.into()]), 0);
+    for _ in 0..5 {
+        assert_eq!(p.call("process_frame_42", &[extension(0)]), 0);
+    }
+    assert_eq!(p.host().poctl_calls, vec![(0x80001, vec![false.into()])]);
+}
@@ -0,0 +1,117 @@
+//! Helpers for the protocol operations handling a frame type. At sender side,
+//! these are `should_send_frame_*`, `prepare_frame_*`, `wire_len_*`,
+//! `write_frame_*`, `log_frame_*`, `on_frame_reserved_*` and
+//! `notify_frame_*`. At receiver side, these are `parse_frame_*` and
+//! `process_frame_*`.
+
+use pluginop_wasm::{Bytes, PluginEnv, quic::{ExtensionFrame, Frame, PacketType, QVal}};
+
+use crate::{input, put_all, save, varint, Error, Result};
+
+/// The inputs of `should_send_frame_*`.
+#[derive(Clone, Copy, Debug)]
+pub struct ShouldSend {
+    /// The type of the packet being built.
+    pub packet_type: PacketType,
+    /// Whether the connection is closing.
+    pub is_closing: bool,
+    /// The number of bytes left in the packet, if provided by the host. Use
+    /// [`ShouldSend::left`] to require it.
+    pub left: Option<usize>,
+}
+
+impl ShouldSend {
+    pub fn get(penv: &PluginEnv) -> Result<Self> {
+        Ok(ShouldSend {
+            packet_type: packet_type(penv, 0)?,
+            is_closing: input(penv, 2)?,
+            left: penv.get_input(3).ok(),
+        })
+    }
+
+    /// The number of bytes left in the packet, failing if the host did not
+    /// provide it.
+    pub fn left(&self) -> Result<usize> {
+        self.left.ok_or(Error::BadInput(3))
+    }
+}
+
+/// Get the packet type input at `index`.
+pub fn packet_type(penv: &PluginEnv, index: u32) -> Result<PacketType> {
+    match input(penv, index)? {
+        QVal::PacketType(pt) => Ok(pt),
+        _ => Err(Error::BadInput(index)),
+    }
+}
+
+/// Get the frame input at `index`.
+pub fn frame(penv: &PluginEnv, index: u32) -> Result<Frame> {
+    match input(penv, index)? {
+        QVal::Frame(f) => Ok(f),
+        _ => Err(Error::BadInput(index)),
+    }
+}
+
+/// Get the extension frame input at `index`.
+pub fn extension_frame(penv: &PluginEnv, index: u32) -> Result<ExtensionFrame> {
+    match frame(penv, index)? {
+        Frame::Extension(e) => Ok(e),
+        _ => Err(Error::BadInput(index)),
+    }
+}
+
+/// Save the frame to send (`prepare_frame_*`) or the parsed one
+/// (`parse_frame_*`).
+pub fn save_frame(penv: &PluginEnv, f: Frame) -> Result<()> {
+    save(penv, f)
+}
+
+/// Save the extension frame with the provided type and tag.
+pub fn save_extension(penv: &PluginEnv, frame_type: u64, tag: u64) -> Result<()> {
+    save_frame(penv, Frame::Extension(ExtensionFrame { frame_type, tag }))
+}
+
+/// The bytes to parse a frame from, as provided to `parse_frame_*`. The frame
+/// type is already consumed.
+pub fn parse_bytes(penv: &PluginEnv) -> Result<Bytes> {
+    input(penv, 0)
+}
+
+/// The length on the wire of a frame of type `frame_type` whose content
+/// takes `len` bytes.
+pub fn wire_len(frame_type: u64, len: usize) -> usize {
+    varint::len(frame_type) + len
+}
+
+/// Save the output of `wire_len_*`.
+pub fn save_wire_len(penv: &PluginEnv, len: usize) -> Result<()> {
+    save(penv, len)
+}
+
+/// Write the whole `frame_bytes` as expected by `write_frame_*`, i.e., to the
+/// bytes in input 1, and save the number of written bytes.
+pub fn write(penv: &mut PluginEnv, frame_bytes: &[u8]) -> Result<()> {
+    let bytes = input::<Bytes>(penv, 1)?;
+    put_all(penv, bytes.tag, frame_bytes)?;
+    save(penv, frame_bytes.len())
+}
+
+/// Same as [`write`], prefixing the `content` with the `frame_type`.
+pub fn write_with_type(penv: &mut PluginEnv, frame_type: u64, content: &[u8]) -> Result<()> {
+    let mut frame_bytes = Vec::with_capacity(wire_len(frame_type, content.len()));
+    varint::write(frame_type, &mut frame_bytes)?;
+    frame_bytes.extend_from_slice(content);
+    write(penv, &frame_bytes)
+}
+
+/// Write the textual representation of the frame, as expected by
+/// `log_frame_*`.
+pub fn log(penv: &mut PluginEnv, s: &str) -> Result<()> {
+    let bytes = input::<Bytes>(penv, 1)?;
+    put_all(penv, bytes.tag, s.as_bytes())
+}
+
+/// Whether the notified frame was lost, as provided to `notify_frame_*`.
+pub fn is_lost(penv: &PluginEnv) -> Result<bool> {
+    input(penv, 1)
+}
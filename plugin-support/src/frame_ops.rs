@@ -0,0 +1,226 @@
+//! Generation of the protocol operations of an extension frame.
+//!
+//! A plugin implements [`FrameOps`] for its frame type, and then calls
+//! [`extension_frame!`](crate::extension_frame) with the hexadecimal
+//! representation of the frame type. The macro exports every
+//! `*_frame_<type>` and `wire_len_<type>` protocol operation with the name
+//! expected by the host, and checks at compile time that it matches
+//! [`FrameOps::FRAME_TYPE`].
+//!
+//! ```ignore
+//! struct MyFrame;
+//!
+//! impl FrameOps for MyFrame {
+//!     const FRAME_TYPE: u64 = 0x42;
+//!     // ...
+//! }
+//!
+//! plugin_support::extension_frame!(MyFrame, "42");
+//! ```
+
+use pluginop_wasm::{Bytes, PluginEnv};
+
+use crate::{frame::{self, ShouldSend}, IntoCode, Result};
+
+/// The behaviour of an extension frame. Frames are identified by the tag
+/// returned by [`FrameOps::prepare`] or [`FrameOps::parse`], which is
+/// provided back to the other operations.
+pub trait FrameOps {
+    /// The type of the frame.
+    const FRAME_TYPE: u64;
+
+    /// Whether a frame should be sent in the packet being built.
+    fn should_send(penv: &mut PluginEnv, ss: ShouldSend) -> Result<bool>;
+
+    /// Prepare a frame to send, returning its tag.
+    fn prepare(penv: &mut PluginEnv) -> Result<u64>;
+
+    /// The length of the frame content, without the frame type.
+    fn wire_len(penv: &mut PluginEnv, tag: u64) -> Result<usize>;
+
+    /// The frame content, without the frame type.
+    fn write(penv: &mut PluginEnv, tag: u64) -> Result<Vec<u8>>;
+
+    /// Parse a frame from `bytes`, whose type is already consumed, returning
+    /// its tag.
+    fn parse(penv: &mut PluginEnv, bytes: Bytes) -> Result<u64>;
+
+    /// Process a received frame.
+    fn process(penv: &mut PluginEnv, tag: u64) -> Result<()>;
+
+    /// The textual representation of the frame.
+    fn log(_penv: &mut PluginEnv, tag: u64) -> Result<String> {
+        Ok(format!("frame 0x{:x} with tag {}", Self::FRAME_TYPE, tag))
+    }
+
+    /// The frame was put in a packet.
+    fn on_frame_reserved(_penv: &mut PluginEnv, _tag: u64) -> Result<()> {
+        Ok(())
+    }
+
+    /// The packet containing the frame was acknowledged or lost.
+    fn notify(_penv: &mut PluginEnv, _tag: u64, _is_lost: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub fn should_send<F: FrameOps>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let ss = ShouldSend::get(penv)?;
+        let out = F::should_send(penv, ss)?;
+        crate::save(penv, out)
+    })();
+    res.into_code()
+}
+
+pub fn prepare<F: FrameOps>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let tag = F::prepare(penv)?;
+        frame::save_extension(penv, F::FRAME_TYPE, tag)
+    })();
+    res.into_code()
+}
+
+pub fn wire_len<F: FrameOps>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let tag = frame::extension_frame(penv, 0)?.tag;
+        let len = F::wire_len(penv, tag)?;
+        frame::save_wire_len(penv, frame::wire_len(F::FRAME_TYPE, len))
+    })();
+    res.into_code()
+}
+
+pub fn write<F: FrameOps>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let tag = frame::extension_frame(penv, 0)?.tag;
+        let content = F::write(penv, tag)?;
+        frame::write_with_type(penv, F::FRAME_TYPE, &content)
+    })();
+    res.into_code()
+}
+
+pub fn log<F: FrameOps>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let tag = frame::extension_frame(penv, 0)?.tag;
+        let s = F::log(penv, tag)?;
+        frame::log(penv, &s)
+    })();
+    res.into_code()
+}
+
+pub fn parse<F: FrameOps>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let bytes = frame::parse_bytes(penv)?;
+        let tag = F::parse(penv, bytes)?;
+        frame::save_extension(penv, F::FRAME_TYPE, tag)
+    })();
+    res.into_code()
+}
+
+pub fn process<F: FrameOps>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let tag = frame::extension_frame(penv, 0)?.tag;
+        F::process(penv, tag)
+    })();
+    res.into_code()
+}
+
+pub fn on_frame_reserved<F: FrameOps>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let tag = frame::extension_frame(penv, 0)?.tag;
+        F::on_frame_reserved(penv, tag)
+    })();
+    res.into_code()
+}
+
+pub fn notify<F: FrameOps>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let tag = frame::extension_frame(penv, 0)?.tag;
+        let is_lost = frame::is_lost(penv)?;
+        F::notify(penv, tag, is_lost)
+    })();
+    res.into_code()
+}
+
+/// Parse the hexadecimal suffix of a protocol operation name, returning
+/// `u64::MAX` if it is not valid.
+pub const fn parse_hex(s: &str) -> u64 {
+    let b = s.as_bytes();
+    if b.is_empty() || b.len() > 16 {
+        return u64::MAX;
+    }
+    let mut v = 0;
+    let mut i = 0;
+    while i < b.len() {
+        let d = match b[i] {
+            c @ b'0'..=b'9' => c - b'0',
+            c @ b'a'..=b'f' => c - b'a' + 10,
+            _ => return u64::MAX,
+        };
+        v = (v << 4) | d as u64;
+        i += 1;
+    }
+    v
+}
+
+/// Export all the protocol operations of the extension frame implemented by
+/// `$frame`, whose type is `$hex` in lowercase hexadecimal without prefix.
+#[macro_export]
+macro_rules! extension_frame {
+    ($frame:ty, $hex:literal) => {
+        const _: () = {
+            use $crate::frame_ops::{self, FrameOps};
+            use $crate::pluginop_wasm::PluginEnv;
+
+            assert!(
+                frame_ops::parse_hex($hex) == <$frame as FrameOps>::FRAME_TYPE,
+                concat!("\"", $hex, "\" is not the lowercase hexadecimal FRAME_TYPE of ", stringify!($frame)),
+            );
+
+            #[export_name = concat!("should_send_frame_", $hex)]
+            pub extern "C" fn should_send(penv: &mut PluginEnv) -> i64 {
+                frame_ops::should_send::<$frame>(penv)
+            }
+
+            #[export_name = concat!("prepare_frame_", $hex)]
+            pub extern "C" fn prepare(penv: &mut PluginEnv) -> i64 {
+                frame_ops::prepare::<$frame>(penv)
+            }
+
+            #[export_name = concat!("wire_len_", $hex)]
+            pub extern "C" fn wire_len(penv: &mut PluginEnv) -> i64 {
+                frame_ops::wire_len::<$frame>(penv)
+            }
+
+            #[export_name = concat!("write_frame_", $hex)]
+            pub extern "C" fn write(penv: &mut PluginEnv) -> i64 {
+                frame_ops::write::<$frame>(penv)
+            }
+
+            #[export_name = concat!("log_frame_", $hex)]
+            pub extern "C" fn log(penv: &mut PluginEnv) -> i64 {
+                frame_ops::log::<$frame>(penv)
+            }
+
+            #[export_name = concat!("parse_frame_", $hex)]
+            pub extern "C" fn parse(penv: &mut PluginEnv) -> i64 {
+                frame_ops::parse::<$frame>(penv)
+            }
+
+            #[export_name = concat!("process_frame_", $hex)]
+            pub extern "C" fn process(penv: &mut PluginEnv) -> i64 {
+                frame_ops::process::<$frame>(penv)
+            }
+
+            #[export_name = concat!("on_frame_reserved_", $hex)]
+            pub extern "C" fn on_frame_reserved(penv: &mut PluginEnv) -> i64 {
+                frame_ops::on_frame_reserved::<$frame>(penv)
+            }
+
+            #[export_name = concat!("notify_frame_", $hex)]
+            pub extern "C" fn notify(penv: &mut PluginEnv) -> i64 {
+                frame_ops::notify::<$frame>(penv)
+            }
+        };
+    };
+}
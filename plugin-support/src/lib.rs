@@ -0,0 +1,136 @@
+//! Helpers shared by the plugins of this repository.
+//!
+//! Protocol operations mostly follow the same sequence of calls to the
+//! [`PluginEnv`]: fetching inputs, writing bytes and saving outputs. This crate
+//! wraps them such that a protocol operation can be written as a function
+//! returning a [`Result`], converted into the `i64` expected by the host with
+//! [`IntoCode::into_code`].
+
+use pluginop_wasm::{PluginEnv, PluginVal};
+
+/// Errors that a protocol operation may return, each mapping to a specific
+/// `i64` return code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A call to the host API failed.
+    Api,
+    /// The input having the given index is missing or has an unexpected type.
+    BadInput(u32),
+    /// The output could not be saved.
+    SaveOutput,
+    /// The bytes could not be read or written as expected.
+    BadBytes,
+    /// The host refused a registration.
+    Registration,
+    /// The plugin state does not allow the operation.
+    InvalidState,
+    /// A received frame or transport parameter is malformed, the plugin
+    /// counterpart of the QUIC FRAME_ENCODING_ERROR.
+    FrameEncoding,
+    /// Ask the host to stop building the current packet. Plugins pacing the
+    /// packets should rather implement the [`send_gate`].
+    StopSending,
+}
+
+impl Error {
+    /// The return code of a protocol operation failing with this error.
+    pub fn code(&self) -> i64 {
+        match self {
+            Error::Api => -1,
+            Error::SaveOutput => -2,
+            Error::BadBytes => -3,
+            Error::Registration => -4,
+            Error::InvalidState => -5,
+            Error::FrameEncoding => -0x07,
+            Error::BadInput(i) => -0x10 - *i as i64,
+            Error::StopSending => -1000,
+        }
+    }
+}
+
+impl From<pluginop_wasm::Error> for Error {
+    fn from(e: pluginop_wasm::Error) -> Self {
+        match e {
+            pluginop_wasm::Error::BadBytes => Error::BadBytes,
+            _ => Error::Api,
+        }
+    }
+}
+
+impl From<varint::Error> for Error {
+    fn from(_: varint::Error) -> Self {
+        Error::FrameEncoding
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Conversion of the result of a protocol operation into its return code.
+pub trait IntoCode {
+    fn into_code(self) -> i64;
+}
+
+impl IntoCode for Result<()> {
+    fn into_code(self) -> i64 {
+        match self {
+            Ok(()) => 0,
+            Err(e) => e.code(),
+        }
+    }
+}
+
+/// Get the input at `index`, failing with [`Error::BadInput`].
+pub fn input<T>(penv: &PluginEnv, index: u32) -> Result<T>
+where
+    T: TryFrom<PluginVal>,
+    <T as TryFrom<PluginVal>>::Error: std::fmt::Debug,
+{
+    penv.get_input(index).map_err(|_| Error::BadInput(index))
+}
+
+/// Get the input at `index` from the already fetched `inputs`.
+pub fn input_from<T>(inputs: &[PluginVal], index: u32) -> Result<T>
+where
+    T: TryFrom<PluginVal>,
+{
+    inputs.get(index as usize).and_then(|v| T::try_from(*v).ok()).ok_or(Error::BadInput(index))
+}
+
+/// Save a single output.
+pub fn save<T: Into<PluginVal>>(penv: &PluginEnv, v: T) -> Result<()> {
+    penv.save_output(v.into()).map_err(|_| Error::SaveOutput)
+}
+
+/// Save all the outputs at once.
+pub fn save_all(penv: &PluginEnv, vals: &[PluginVal]) -> Result<()> {
+    penv.save_outputs(vals).map_err(|_| Error::SaveOutput)
+}
+
+/// Write `b` to the bytes having the given `tag`, ensuring that all of them
+/// were written.
+pub fn put_all(penv: &mut PluginEnv, tag: u64, b: &[u8]) -> Result<()> {
+    match penv.put_bytes(tag, b)? {
+        l if l == b.len() => Ok(()),
+        _ => Err(Error::BadBytes),
+    }
+}
+
+/// Read exactly `len` bytes having the given `tag`.
+pub fn get_exact(penv: &mut PluginEnv, tag: u64, len: u64) -> Result<Vec<u8>> {
+    match penv.get_bytes(tag, len) {
+        Ok(b) if b.len() as u64 == len => Ok(b),
+        Ok(_) => Err(Error::FrameEncoding),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[doc(hidden)]
+pub use pluginop_wasm;
+
+pub mod frame;
+pub mod frame_ops;
+pub mod manifest;
+pub mod poctl;
+pub mod send_gate;
+pub mod tp;
+pub mod varint;
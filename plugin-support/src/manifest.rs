@@ -0,0 +1,146 @@
+//! Manifest embedded in the WebAssembly module of a plugin, declaring what it
+//! registers and which identifiers it uses, such that plugins loaded together
+//! can be checked for collisions without running them.
+//!
+//! The manifest is stored in the [`SECTION`] custom section as text, one
+//! declaration per line, e.g., `frame 0xbd`. It is generated by
+//! [`plugin_manifest!`](crate::plugin_manifest).
+//!
+//! ```ignore
+//! plugin_support::plugin_manifest! {
+//!     name: "my-plugin",
+//!     frames: [0x42],
+//!     transport_parameters: [],
+//!     timers: [],
+//!     plugin_controls: [poctl::ids::MY_PLUGIN_CONTROL],
+//!     calls: [],
+//! }
+//! ```
+
+/// The name of the custom section holding the manifest.
+pub const SECTION: &str = "pluginop.manifest";
+
+/// The declarations of a plugin.
+pub struct Manifest<'a> {
+    /// The name of the plugin.
+    pub name: &'a str,
+    /// The frame types it registers.
+    pub frames: &'a [u64],
+    /// The transport parameters it registers.
+    pub transport_parameters: &'a [u64],
+    /// The timers it handles, i.e., the `on_plugin_timeout_*` it exports.
+    pub timers: &'a [u64],
+    /// The plugin controls it provides, i.e., the `plugin_control_*` it
+    /// exports.
+    pub plugin_controls: &'a [u64],
+    /// The plugin controls of other plugins that it invokes.
+    pub calls: &'a [u64],
+}
+
+/// The keys of the declarations, in the order of the [`Manifest`] fields.
+pub const KEYS: [&str; 5] = ["frame", "transport_parameter", "timer", "plugin_control", "call"];
+
+const fn hex_len(mut v: u64) -> usize {
+    let mut len = 1;
+    while v >= 16 {
+        v >>= 4;
+        len += 1;
+    }
+    len
+}
+
+/// Copy `src` at `at` in `out`, returning the position after it.
+const fn put<const N: usize>(out: &mut [u8; N], mut at: usize, src: &[u8]) -> usize {
+    let mut i = 0;
+    while i < src.len() {
+        out[at] = src[i];
+        at += 1;
+        i += 1;
+    }
+    at
+}
+
+const fn put_hex<const N: usize>(out: &mut [u8; N], at: usize, v: u64) -> usize {
+    let at = put(out, at, b"0x");
+    let len = hex_len(v);
+    let mut i = 0;
+    while i < len {
+        let d = ((v >> (4 * (len - 1 - i))) & 0xf) as u8;
+        out[at + i] = if d < 10 { b'0' + d } else { b'a' + d - 10 };
+        i += 1;
+    }
+    at + len
+}
+
+impl Manifest<'_> {
+    const fn lists(&self) -> [&[u64]; 5] {
+        [self.frames, self.transport_parameters, self.timers, self.plugin_controls, self.calls]
+    }
+
+    /// The length of the encoded manifest.
+    pub const fn encoded_len(&self) -> usize {
+        let mut len = "name ".len() + self.name.len() + 1;
+        let lists = self.lists();
+        let mut l = 0;
+        while l < lists.len() {
+            let mut i = 0;
+            while i < lists[l].len() {
+                len += KEYS[l].len() + " 0x".len() + hex_len(lists[l][i]) + 1;
+                i += 1;
+            }
+            l += 1;
+        }
+        len
+    }
+
+    /// Encode the manifest, `N` being its [`Manifest::encoded_len`].
+    pub const fn encode<const N: usize>(&self) -> [u8; N] {
+        let mut out = [0; N];
+        let mut at = put(&mut out, 0, b"name ");
+        at = put(&mut out, at, self.name.as_bytes());
+        at = put(&mut out, at, b"\n");
+        let lists = self.lists();
+        let mut l = 0;
+        while l < lists.len() {
+            let mut i = 0;
+            while i < lists[l].len() {
+                at = put(&mut out, at, KEYS[l].as_bytes());
+                at = put(&mut out, at, b" ");
+                at = put_hex(&mut out, at, lists[l][i]);
+                at = put(&mut out, at, b"\n");
+                i += 1;
+            }
+            l += 1;
+        }
+        out
+    }
+}
+
+/// Embed the manifest of the plugin in its WebAssembly module. All the lists
+/// must be provided, possibly empty, and accept constants.
+#[macro_export]
+macro_rules! plugin_manifest {
+    (
+        name: $name:literal,
+        frames: [$($frame:expr),* $(,)?],
+        transport_parameters: [$($tp:expr),* $(,)?],
+        timers: [$($timer:expr),* $(,)?],
+        plugin_controls: [$($poctl:expr),* $(,)?],
+        calls: [$($call:expr),* $(,)?] $(,)?
+    ) => {
+        const _: () = {
+            const MANIFEST: $crate::manifest::Manifest<'static> = $crate::manifest::Manifest {
+                name: $name,
+                frames: &[$($frame),*],
+                transport_parameters: &[$($tp),*],
+                timers: &[$($timer),*],
+                plugin_controls: &[$($poctl),*],
+                calls: &[$($call),*],
+            };
+
+            #[used]
+            #[cfg_attr(target_arch = "wasm32", link_section = "pluginop.manifest")]
+            static SECTION: [u8; MANIFEST.encoded_len()] = MANIFEST.encode();
+        };
+    };
+}
@@ -0,0 +1,73 @@
+//! Helpers for plugin control operations, which only exchange [`PluginVal`]s
+//! with the application.
+
+use pluginop_wasm::PluginVal;
+
+/// The identifiers of the plugin controls provided by the plugins of this
+/// repository. They share a single namespace once plugins are loaded
+/// together, so new ones must be added here, and plugins invoking the ones of
+/// another plugin refer to them from here.
+pub mod ids {
+    /// probe-path: request sending a PATH_CHALLENGE.
+    pub const PROBE_PATH_CHALLENGE: u64 = 0x1;
+    /// bdp-frame: set the token to send (client) or issue one (server).
+    pub const BDP_SET_TOKEN: u64 = 0x80000;
+    /// privacy-padding: enable or disable the padding.
+    pub const PRIVACY_PADDING_ENABLE: u64 = 0x80001;
+    /// bdp-frame: configure the token lifetime and secret of the server.
+    pub const BDP_CONFIGURE_SERVER: u64 = 0x80002;
+    /// bdp-frame: get the token received by the client.
+    pub const BDP_GET_TOKEN: u64 = 0x80003;
+    /// bdp-frame: set the number of RTTs of the careful resume.
+    pub const BDP_SET_RAMP_RTTS: u64 = 0x80004;
+    /// bdp-frame: get the BDP estimated over the connection.
+    pub const BDP_GET_CAPTURED: u64 = 0x80005;
+    /// privacy-padding: select the traffic-shaping profile.
+    pub const PRIVACY_PADDING_SET_PROFILE: u64 = 0x80010;
+    /// privacy-padding: append a chunk of the samples of the distribution or
+    /// trace profiles.
+    pub const PRIVACY_PADDING_LOAD_SAMPLES: u64 = 0x80011;
+    /// privacy-padding: configure the cover traffic.
+    pub const PRIVACY_PADDING_SET_COVER: u64 = 0x80012;
+    /// privacy-padding: get the overhead and delay statistics.
+    pub const PRIVACY_PADDING_GET_STATS: u64 = 0x80013;
+    /// privacy-padding: set the file receiving the statistics at close.
+    pub const PRIVACY_PADDING_SET_STATS_FILE: u64 = 0x80014;
+    /// privacy-padding: select the size of the padded packets.
+    pub const PRIVACY_PADDING_SET_SIZES: u64 = 0x80015;
+    /// logger: set the prefix of the trace file names.
+    pub const LOGGER_SET_PREFIX: u64 = 0x80020;
+    /// logger: set the enabled categories of events.
+    pub const LOGGER_SET_CATEGORIES: u64 = 0x80021;
+    /// logger: set the format of the trace.
+    pub const LOGGER_SET_FORMAT: u64 = 0x80022;
+    /// logger: start or stop logging.
+    pub const LOGGER_ENABLE: u64 = 0x80023;
+    /// logger: set the rotation size and the maximum number of files.
+    pub const LOGGER_SET_LIMITS: u64 = 0x80024;
+}
+
+/// Collect the `U64` plugin values starting at `from` as big-endian bytes.
+pub fn words_to_bytes(vals: &[PluginVal], from: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for v in vals.iter().skip(from) {
+        match v {
+            PluginVal::U64(w) => bytes.extend_from_slice(&w.to_be_bytes()),
+            _ => return None,
+        }
+    }
+    Some(bytes)
+}
+
+/// Split `bytes` into big-endian `U64` plugin values, the last one being
+/// padded with zeros.
+pub fn bytes_to_words(bytes: &[u8]) -> Vec<PluginVal> {
+    bytes
+        .chunks(8)
+        .map(|c| {
+            let mut word = [0u8; 8];
+            word[..c.len()].copy_from_slice(c);
+            PluginVal::U64(u64::from_be_bytes(word))
+        })
+        .collect()
+}
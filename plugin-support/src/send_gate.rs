@@ -0,0 +1,84 @@
+//! The send gate, letting a plugin pace the sending of packets.
+//!
+//! Before building a packet, the host calls the `send_gate` protocol
+//! operation with the type of the packet (input 0) and the current time
+//! (input 1). The plugin answers whether the packet may be sent now (a single
+//! `true` output) or not, along with the time at which the host should try
+//! again (`false` followed by that time). The host does not build the packet
+//! in the latter case, and wakes up at the provided time.
+//!
+//! The host of `pluginop` 0.1 does not call this operation yet, it only sees
+//! it as an `Other` one, so the gate has no effect there. Plugins needing to
+//! pace the packets on such a host can only stop the building of the packets
+//! with [`Error::StopSending`], as the opt-in `stop-frame` feature of
+//! privacy-padding does.
+//!
+//! A plugin implements [`SendGate`] and then calls
+//! [`send_gate!`](crate::send_gate).
+//!
+//! ```ignore
+//! struct Pacer;
+//!
+//! impl SendGate for Pacer {
+//!     fn may_send(penv: &mut PluginEnv, packet_type: PacketType, now: UnixInstant) -> Result<Decision> {
+//!         // ...
+//!     }
+//! }
+//!
+//! plugin_support::send_gate!(Pacer);
+//! ```
+
+use pluginop_wasm::{PluginEnv, PluginVal, UnixInstant, quic::PacketType};
+
+use crate::{frame, input, save_all, IntoCode, Result};
+#[cfg(doc)]
+use crate::Error;
+
+/// The name of the protocol operation.
+pub const OPERATION: &str = "send_gate";
+
+/// Whether a packet may be sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    Send,
+    /// Do not send before the provided time.
+    WaitUntil(UnixInstant),
+}
+
+impl Decision {
+    /// The outputs of the protocol operation.
+    pub fn outputs(&self) -> Vec<PluginVal> {
+        match *self {
+            Decision::Send => vec![true.into()],
+            Decision::WaitUntil(t) => vec![false.into(), t.into()],
+        }
+    }
+}
+
+pub trait SendGate {
+    /// Whether a packet of `packet_type` may be sent at `now`.
+    fn may_send(penv: &mut PluginEnv, packet_type: PacketType, now: UnixInstant) -> Result<Decision>;
+}
+
+pub fn gate<G: SendGate>(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let packet_type = frame::packet_type(penv, 0)?;
+        let now = input::<UnixInstant>(penv, 1)?;
+        let decision = G::may_send(penv, packet_type, now)?;
+        save_all(penv, &decision.outputs())
+    })();
+    res.into_code()
+}
+
+/// Export the `send_gate` protocol operation implemented by `$gate`.
+#[macro_export]
+macro_rules! send_gate {
+    ($gate:ty) => {
+        const _: () = {
+            #[export_name = "send_gate"]
+            pub extern "C" fn send_gate(penv: &mut $crate::pluginop_wasm::PluginEnv) -> i64 {
+                $crate::send_gate::gate::<$gate>(penv)
+            }
+        };
+    };
+}
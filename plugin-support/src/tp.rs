@@ -0,0 +1,16 @@
+//! Writing of transport parameters.
+
+use pluginop_wasm::{Bytes, PluginEnv};
+
+use crate::{input, put_all, varint, Result};
+
+/// Write the transport parameter `id` with the provided `value`, as expected
+/// by `write_transport_parameter_*` protocol operations.
+pub fn write(penv: &mut PluginEnv, id: u64, value: &[u8]) -> Result<()> {
+    let bytes = input::<Bytes>(penv, 0)?;
+    let mut tp_bytes = Vec::with_capacity(varint::len(id) + varint::len(value.len() as u64) + value.len());
+    varint::write(id, &mut tp_bytes)?;
+    varint::write(value.len() as u64, &mut tp_bytes)?;
+    tp_bytes.extend_from_slice(value);
+    put_all(penv, bytes.tag, &tp_bytes)
+}
@@ -0,0 +1,106 @@
+//! QUIC variable-length integers, as defined in Section 16 of RFC 9000.
+
+use pluginop_wasm::PluginEnv;
+
+/// The largest value that can be encoded as a varint.
+pub const MAX: u64 = (1 << 62) - 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The value is larger than [`MAX`].
+    TooLarge,
+    /// The buffer ends in the middle of the varint.
+    BufferTooShort,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The number of bytes needed to encode `v`.
+pub fn len(v: u64) -> usize {
+    match v {
+        0..=0x3f => 1,
+        0x40..=0x3fff => 2,
+        0x4000..=0x3fff_ffff => 4,
+        _ => 8,
+    }
+}
+
+/// Append the encoding of `v` to `buf`.
+pub fn write(v: u64, buf: &mut Vec<u8>) -> Result<()> {
+    match len(v) {
+        _ if v > MAX => return Err(Error::TooLarge),
+        1 => buf.push(v as u8),
+        2 => buf.extend_from_slice(&(v as u16 | 0x4000).to_be_bytes()),
+        4 => buf.extend_from_slice(&(v as u32 | 0x8000_0000).to_be_bytes()),
+        _ => buf.extend_from_slice(&(v | 0xc000_0000_0000_0000).to_be_bytes()),
+    };
+    Ok(())
+}
+
+/// Decode the varint at the start of `buf`, returning its value and the
+/// number of bytes it used.
+pub fn decode(buf: &[u8]) -> Result<(u64, usize)> {
+    let first = *buf.first().ok_or(Error::BufferTooShort)?;
+    let len = 1 << (first >> 6);
+    let bytes = buf.get(1..len).ok_or(Error::BufferTooShort)?;
+    let v = bytes.iter().fold((first & 0x3f) as u64, |acc, b| (acc << 8) | *b as u64);
+    Ok((v, len))
+}
+
+/// Read a varint from the bytes having the given `tag`.
+pub fn read(penv: &mut PluginEnv, tag: u64) -> Result<u64> {
+    let mut buf = penv.get_bytes(tag, 1).map_err(|_| Error::BufferTooShort)?;
+    let len = 1 << (buf.first().ok_or(Error::BufferTooShort)? >> 6);
+    if len > 1 {
+        buf.extend(penv.get_bytes(tag, len as u64 - 1).map_err(|_| Error::BufferTooShort)?);
+    }
+    decode(&buf).map(|(v, _)| v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(v: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write(v, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn boundaries() {
+        let cases: [(u64, &[u8]); 8] = [
+            (0x3f, &[0x3f]),
+            (0x40, &[0x40, 0x40]),
+            (0x3fff, &[0x7f, 0xff]),
+            (0x4000, &[0x80, 0x00, 0x40, 0x00]),
+            (0x3fff_ffff, &[0xbf, 0xff, 0xff, 0xff]),
+            (0x4000_0000, &[0xc0, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00]),
+            (MAX, &[0xff; 8]),
+            (0, &[0x00]),
+        ];
+        for (v, bytes) in cases {
+            assert_eq!(encode(v), bytes, "{v:#x}");
+            assert_eq!(len(v), bytes.len(), "{v:#x}");
+            assert_eq!(decode(bytes), Ok((v, bytes.len())), "{v:#x}");
+        }
+    }
+
+    #[test]
+    fn too_large() {
+        let mut buf = Vec::new();
+        assert_eq!(write(MAX + 1, &mut buf), Err(Error::TooLarge));
+        assert_eq!(write(u64::MAX, &mut buf), Err(Error::TooLarge));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(decode(&[]), Err(Error::BufferTooShort));
+        assert_eq!(decode(&[0x40]), Err(Error::BufferTooShort));
+        assert_eq!(decode(&[0x80, 0x00, 0x40]), Err(Error::BufferTooShort));
+        assert_eq!(decode(&[0xc0, 0, 0, 0, 0, 0, 0]), Err(Error::BufferTooShort));
+        // Trailing bytes are left to the caller.
+        assert_eq!(decode(&[0x40, 0x40, 0xff]), Ok((0x40, 2)));
+    }
+}
@@ -0,0 +1,130 @@
+//! Cover traffic, i.e., chaff packets sent when the connection is idle such
+//! that observers see a steady stream of packets. A chaff packet holds a PING
+//! frame filled with PADDING: padding alone is not ack-eliciting, so it would
+//! not make the host build a packet.
+//!
+//! Once no 1-RTT packet was sent for an interval, the chaff timer schedules a
+//! chaff packet, provided that the congestion window and the overhead budget
+//! allow it. The budget is a number of bytes per second, accumulated up to
+//! one second of chaff, or a single chaff packet if larger.
+
+use pluginop_wasm::{Duration, PluginEnv, UnixInstant, quic::{ConnectionField, Frame, PacketType, PingFrame, RecoveryField}};
+use plugin_support::{frame, input, save, Error, IntoCode, Result};
+
+use crate::{profile, PLUGIN_DATA};
+
+/// The timer checking whether to send a chaff packet.
+pub const CHAFF_TIMER: u64 = 8;
+
+#[derive(Default)]
+pub struct Cover {
+    /// The idle time after which a chaff packet is sent, if enabled.
+    interval: Option<Duration>,
+    /// The chaff bytes allowed per second.
+    budget: u64,
+    /// The chaff bytes that can be sent now.
+    tokens: u64,
+    refilled: Option<UnixInstant>,
+    /// When the last 1-RTT packet was built.
+    last_packet: Option<UnixInstant>,
+    /// Whether the next 1-RTT packet is a chaff one.
+    pending: bool,
+}
+
+impl Cover {
+    /// Account for a 1-RTT packet being built at `now`.
+    pub fn on_packet(&mut self, now: UnixInstant) {
+        self.last_packet = Some(now);
+        self.pending = false;
+    }
+
+    /// Earn the tokens since the last refill. The bucket holds at least one
+    /// chaff packet of `size` bytes, otherwise a budget below it would never
+    /// allow any chaff.
+    fn refill(&mut self, now: UnixInstant, size: u64) {
+        let elapsed = self.refilled.map_or(Duration::ZERO, |r| now.saturating_duration_since(r));
+        let earned = (self.budget as u128 * elapsed.as_micros() / 1_000_000) as u64;
+        self.tokens = self.tokens.saturating_add(earned).min(self.budget.max(size));
+        self.refilled = Some(now);
+    }
+}
+
+/// Whether a chaff packet of `size` bytes fits in the congestion window.
+fn fits_in_cwnd(penv: &PluginEnv, size: u64) -> bool {
+    let in_flight: u64 = penv.get_recovery(RecoveryField::BytesInFlight);
+    let cwnd: u64 = penv.get_recovery(RecoveryField::CongestionWindow);
+    in_flight + size <= cwnd
+}
+
+#[no_mangle]
+pub extern "C" fn on_plugin_timeout_8(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let now = penv.get_unix_instant()?;
+        let pd = PLUGIN_DATA.get_mut();
+        let Some(interval) = pd.cover.interval else { return Ok(()) };
+        let may_send = pd.enable && !pd.is_paused(now);
+        let size = profile::packet_size(penv);
+        let cover = &mut pd.cover;
+        cover.refill(now, size);
+        // Check again once the connection would be idle.
+        let next = match cover.last_packet {
+            Some(t) if now.saturating_duration_since(t) < interval => t + interval,
+            _ => {
+                let established: bool = penv.get_connection(ConnectionField::IsEstablished)?;
+                if may_send && established && !cover.pending && cover.tokens >= size && fits_in_cwnd(penv, size) {
+                    cover.tokens -= size;
+                    cover.pending = true;
+                }
+                now + interval
+            },
+        };
+        penv.set_timer(next, CHAFF_TIMER, CHAFF_TIMER)?;
+        Ok(())
+    })();
+    res.into_code()
+}
+
+/// Send a PING in the chaff packets, the padding filling the rest.
+#[no_mangle]
+pub extern "C" fn should_send_frame_1(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let ss = frame::ShouldSend::get(penv)?;
+        save(penv, PLUGIN_DATA.cover.pending && ss.packet_type == PacketType::Short && !ss.is_closing)
+    })();
+    res.into_code()
+}
+
+#[no_mangle]
+pub extern "C" fn prepare_frame_1(penv: &mut PluginEnv) -> i64 {
+    PLUGIN_DATA.get_mut().stats.on_chaff();
+    frame::save_frame(penv, Frame::Ping(PingFrame)).into_code()
+}
+
+/// Configure the cover traffic: a chaff packet is sent after input 0
+/// microseconds without 1-RTT packet, 0 disabling it, within a budget of
+/// input 1 bytes per second.
+#[no_mangle]
+pub extern "C" fn plugin_control_80012(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let interval: u64 = input(penv, 0)?;
+        let budget: u64 = input(penv, 1)?;
+        let now = penv.get_unix_instant()?;
+        let cover = &mut PLUGIN_DATA.get_mut().cover;
+        if interval == 0 {
+            cover.interval = None;
+            cover.pending = false;
+            return Ok(());
+        }
+        if budget == 0 {
+            return Err(Error::BadInput(1));
+        }
+        let interval = Duration::from_micros(interval);
+        let armed = cover.interval.is_some();
+        *cover = Cover { interval: Some(interval), budget, refilled: Some(now), last_packet: cover.last_packet, ..Default::default() };
+        if !armed {
+            penv.set_timer(now + interval, CHAFF_TIMER, CHAFF_TIMER)?;
+        }
+        Ok(())
+    })();
+    res.into_code()
+}
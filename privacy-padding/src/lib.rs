@@ -1,150 +1,194 @@
-use pluginop_wasm::{PluginEnv, PluginCell, UnixInstant, Duration, quic::{QVal, ConnectionField, Registration, Frame, PaddingFrame, FrameSendKind, FrameSendOrder, FrameRegistration, PacketType}, Bytes};
+use pluginop_wasm::{PluginEnv, PluginCell, UnixInstant, quic::{ConnectionField, Registration, Frame, PaddingFrame, FrameSendKind, FrameSendOrder, FrameRegistration, PacketType}};
+use plugin_support::{frame, input, plugin_manifest, poctl::ids, save, send_gate::{Decision, SendGate}, Error, IntoCode, Result};
 use lazy_static::lazy_static;
 
-#[derive(Debug)]
+use cover::Cover;
+use profile::Profile;
+use sizing::Sizing;
+use stats::Stats;
+
 struct PluginData {
-    stop_sending: bool,
-    rng: Option<fastrand::Rng>,
+    /// No 1-RTT packet is sent before this time.
+    next_send: Option<UnixInstant>,
+    rng: fastrand::Rng,
     enable: bool,
+    /// The profile drawing the pauses between the packets.
+    profile: Profile,
+    /// The samples loaded for the next profile.
+    samples: Vec<u8>,
+    cover: Cover,
+    stats: Stats,
+    sizing: Sizing,
+    /// The size to which the current packet is padded, `None` filling it.
+    pad_to: Option<u64>,
+}
+
+#[cfg(feature = "stop-frame")]
+plugin_manifest! {
+    name: "privacy-padding",
+    frames: [stop_frame::STOP_FRAME_TYPE, 0x0, 0x1],
+    transport_parameters: [],
+    timers: [stop_frame::RESUME_TIMER, cover::CHAFF_TIMER],
+    plugin_controls: [ids::PRIVACY_PADDING_ENABLE, ids::PRIVACY_PADDING_SET_PROFILE, ids::PRIVACY_PADDING_LOAD_SAMPLES, ids::PRIVACY_PADDING_SET_COVER, ids::PRIVACY_PADDING_GET_STATS, ids::PRIVACY_PADDING_SET_STATS_FILE, ids::PRIVACY_PADDING_SET_SIZES],
+    calls: [],
+}
+
+#[cfg(not(feature = "stop-frame"))]
+plugin_manifest! {
+    name: "privacy-padding",
+    frames: [0x0, 0x1],
+    transport_parameters: [],
+    timers: [cover::CHAFF_TIMER],
+    plugin_controls: [ids::PRIVACY_PADDING_ENABLE, ids::PRIVACY_PADDING_SET_PROFILE, ids::PRIVACY_PADDING_LOAD_SAMPLES, ids::PRIVACY_PADDING_SET_COVER, ids::PRIVACY_PADDING_GET_STATS, ids::PRIVACY_PADDING_SET_STATS_FILE, ids::PRIVACY_PADDING_SET_SIZES],
+    calls: [],
 }
 
 lazy_static! {
     static ref PLUGIN_DATA: PluginCell<PluginData> = PluginCell::new(PluginData {
-        stop_sending: false,
-        rng: None,
+        next_send: None,
+        rng: fastrand::Rng::with_seed(0),
         enable: true,
+        profile: Profile::default(),
+        samples: Vec::new(),
+        cover: Cover::default(),
+        stats: Stats::default(),
+        sizing: Sizing::default(),
+        pad_to: None,
     });
 }
 
 // Initialize the plugin.
 #[no_mangle]
-pub extern fn init(penv: &mut PluginEnv) -> i64 {
-    let now = match penv.get_unix_instant() {
-        Ok(n) => n.secs() + n.subsec_nanos() as u64,
-        Err(_) => return -1,
-    };
-    PLUGIN_DATA.get_mut().rng = Some(fastrand::Rng::with_seed(now));
-    penv.enable();
-    // Trick here.
-    match penv.register(Registration::Frame(FrameRegistration::new(0xaaaa, FrameSendOrder::First, FrameSendKind::OncePerPacket, false, true))) {
-        Ok(()) => {},
-        _ => return -1,
-    };
-    match penv.register(Registration::Frame(FrameRegistration::new(0x0, FrameSendOrder::End, FrameSendKind::OncePerPacket, false, true))) {
-        Ok(()) => 0,
-        _ => -1,
-    }
+pub extern "C" fn init(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let now = penv.get_unix_instant()?;
+        PLUGIN_DATA.get_mut().rng = fastrand::Rng::with_seed(now.secs() + now.subsec_nanos() as u64);
+        penv.enable();
+        #[cfg(feature = "stop-frame")]
+        stop_frame::register(penv)?;
+        #[cfg(not(feature = "stop-frame"))]
+        penv.print("privacy-padding: the packets are only paused by hosts calling the send_gate protocol operation");
+        penv.register(Registration::Frame(FrameRegistration::new(0x0, FrameSendOrder::End, FrameSendKind::OncePerPacket, false, true)))
+            .map_err(|_| Error::Registration)?;
+        // The PING of the chaff packets.
+        penv.register(Registration::Frame(FrameRegistration::new(0x1, FrameSendOrder::First, FrameSendKind::OncePerPacket, true, true)))
+            .map_err(|_| Error::Registration)
+    })();
+    res.into_code()
 }
 
 // This function determines if there are plugin frames that must be
 // sent now or not.
 #[no_mangle]
-pub extern fn should_send_frame_0(penv: &mut PluginEnv) -> i64 {
-    let pkt_type = match penv.get_input::<QVal>(0) {
-        Ok(QVal::PacketType(pt)) => pt,
-        _ => return -1,
-    };
-    let left = match penv.get_input::<usize>(3) {
-        Ok(u) => u,
-        _ => return -2,
-    };
-    let now = match penv.get_input::<UnixInstant>(4) {
-        Ok(u) => u,
-        _ => return -3,
-    };
-    let established: bool = match penv.get_connection(ConnectionField::IsEstablished) {
-        Ok(b) => b,
-        _ => return -5,
-    };
-    // Let suspend the sending if we are too early. This is done by calling prepare frame and giving an error.
-    let out = left > 0 && (pkt_type != PacketType::Short || PLUGIN_DATA.enable);
-    if pkt_type == PacketType::Short && established {
-        PLUGIN_DATA.get_mut().stop_sending = true;
-        let pause = PLUGIN_DATA.get_mut().rng.as_mut().unwrap().u64(..10000);
-        let next_sending = now + Duration::from_micros(pause);
-        if penv.set_timer(next_sending, 1, 7).is_err() {
-            return -6;
+pub extern "C" fn should_send_frame_0(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let ss = frame::ShouldSend::get(penv)?;
+        let left = ss.left()?;
+        let now = input::<UnixInstant>(penv, 4)?;
+        let established: bool = penv.get_connection(ConnectionField::IsEstablished)?;
+        let mut out = left > 0 && (ss.packet_type != PacketType::Short || PLUGIN_DATA.enable);
+        let pd = PLUGIN_DATA.get_mut();
+        pd.pad_to = None;
+        if ss.packet_type == PacketType::Short {
+            pd.cover.on_packet(now);
+            let max = profile::packet_size(penv);
+            pd.pad_to = pd.sizing.pad_to(&mut pd.rng, max.saturating_sub(left as u64));
+            out &= sizing::padding_len(pd.pad_to, left as u64, max) > 0;
         }
-    }
-    match penv.save_output(out.into()) {
-        Ok(()) => 0,
-        Err(_) => -4,
-    }
+        if ss.packet_type == PacketType::Short && established {
+            // The send gate holds the next packet until the pause elapsed.
+            let pd = PLUGIN_DATA.get_mut();
+            let pause = pd.profile.next_pause(&mut pd.rng, profile::packet_size(penv));
+            pd.next_send = Some(now + pause);
+            #[cfg(feature = "stop-frame")]
+            stop_frame::arm(penv, now + pause)?;
+        }
+        save(penv, out)
+    })();
+    res.into_code()
 }
 
-#[no_mangle]
-pub extern fn should_send_frame_aaaa(penv: &mut PluginEnv) -> i64 {
-    match penv.save_output((PLUGIN_DATA.enable && PLUGIN_DATA.stop_sending).into()) {
-        Ok(()) => 0,
-        Err(_) => -4,
+impl PluginData {
+    /// Whether the 1-RTT packets are held at `now`.
+    fn is_paused(&self, now: UnixInstant) -> bool {
+        self.enable && self.next_send.is_some_and(|t| now < t)
     }
 }
 
-#[no_mangle]
-pub extern fn prepare_frame_0(penv: &mut PluginEnv) -> i64 {
-    let left = match penv.get_input::<usize>(1) {
-        Ok(u) => u,
-        _ => return -2,
-    };
-    match penv.save_output(Frame::Padding(PaddingFrame { length: left as u64 }).into()) {
-        Ok(()) => 0,
-        _ => -1,
+/// Whether a packet of `packet_type` may be sent at `now`.
+fn gate(packet_type: PacketType, now: UnixInstant) -> Decision {
+    if packet_type != PacketType::Short {
+        return Decision::Send;
+    }
+    let pd = PLUGIN_DATA.get_mut();
+    let held = pd.is_paused(now);
+    pd.stats.on_gate(now, held);
+    match pd.next_send {
+        Some(t) if held => Decision::WaitUntil(t),
+        _ => Decision::Send,
     }
 }
 
-#[no_mangle]
-pub extern fn wire_len_0(penv: &mut PluginEnv) -> i64 {
-    let p = match penv.get_input::<QVal>(0) {
-        Ok(QVal::Frame(Frame::Padding(p))) => p,
-        _ => return -2,
-    };
-    match penv.save_output((p.length as usize).into()) {
-        Ok(()) => 0,
-        _ => -1,
+struct Pacer;
+
+impl SendGate for Pacer {
+    fn may_send(_penv: &mut PluginEnv, packet_type: PacketType, now: UnixInstant) -> Result<Decision> {
+        Ok(gate(packet_type, now))
     }
 }
 
+plugin_support::send_gate!(Pacer);
+
 #[no_mangle]
-pub extern fn write_frame_0(penv: &mut PluginEnv) -> i64 {
-    let p = match penv.get_input::<QVal>(0) {
-        Ok(QVal::Frame(Frame::Padding(p))) => p,
-        _ => return -2,
-    };
-    let bytes = match penv.get_input::<Bytes>(1) {
-        Ok(b) => b,
-        _ => return -3,
-    };
-    // TODO: check if there is at least 3 bytes.
-    let frame_bytes: Vec<u8> = vec![0x00; p.length as usize];
-    match penv.put_bytes(bytes.tag, &frame_bytes) {
-        Ok(v) if v == p.length as usize => {},
-        _ => return -4,
-    };
-    match penv.save_output(frame_bytes.len().into()) {
-        Ok(()) => 0,
-        _ => -1,
-    }
+pub extern "C" fn prepare_frame_0(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let left = input::<usize>(penv, 1)?;
+        let length = sizing::padding_len(PLUGIN_DATA.pad_to, left as u64, profile::packet_size(penv));
+        frame::save_frame(penv, Frame::Padding(PaddingFrame { length }))
+    })();
+    res.into_code()
 }
 
+fn padding(penv: &PluginEnv) -> Result<PaddingFrame> {
+    match frame::frame(penv, 0)? {
+        Frame::Padding(p) => Ok(p),
+        _ => Err(Error::BadInput(0)),
+    }
+}
 
 #[no_mangle]
-pub extern fn prepare_frame_aaaa(_penv: &mut PluginEnv) -> i64 {
-    // Specific error code to stop the sending processing.
-    return -1000;
+pub extern "C" fn wire_len_0(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let p = padding(penv)?;
+        frame::save_wire_len(penv, p.length as usize)
+    })();
+    res.into_code()
 }
 
 #[no_mangle]
-pub extern fn on_plugin_timeout_7(_penv: &mut PluginEnv) -> i64 {
-    PLUGIN_DATA.get_mut().stop_sending = false;
-    0
+pub extern "C" fn write_frame_0(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let p = padding(penv)?;
+        // TODO: check if there is at least 3 bytes.
+        frame::write(penv, &vec![0x00; p.length as usize])?;
+        PLUGIN_DATA.get_mut().stats.on_padding(p.length);
+        Ok(())
+    })();
+    res.into_code()
 }
 
 #[no_mangle]
-pub extern fn plugin_control_80001(penv: &mut PluginEnv) -> i64 {
-    let enable = match penv.get_input::<bool>(0) {
-        Ok(n) => n,
-        _ => return -2,
-    };
-    PLUGIN_DATA.get_mut().enable = enable;
-    0
-}
\ No newline at end of file
+pub extern "C" fn plugin_control_80001(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        PLUGIN_DATA.get_mut().enable = input(penv, 0)?;
+        Ok(())
+    })();
+    res.into_code()
+}
+
+pub mod cover;
+pub mod profile;
+pub mod sizing;
+pub mod stats;
+#[cfg(feature = "stop-frame")]
+pub mod stop_frame;
@@ -0,0 +1,119 @@
+//! Traffic-shaping profiles, drawing the pause before sending each 1-RTT
+//! packet. They are selected by the application with the
+//! `PRIVACY_PADDING_SET_PROFILE` plugin control.
+//!
+//! The distribution and trace profiles use samples from a text file, one
+//! inter-packet gap in microseconds per line, empty lines and lines starting
+//! with `#` being ignored. Plugins cannot read files, so the application
+//! passes the content of the file with `PRIVACY_PADDING_LOAD_SAMPLES`, in as
+//! many chunks as needed, before selecting the profile.
+
+use pluginop_wasm::{Duration, PluginEnv, quic::ConnectionField};
+use plugin_support::{input, poctl, Error, IntoCode, Result};
+
+use crate::PLUGIN_DATA;
+
+/// The largest pause of the default profile.
+pub const DEFAULT_MAX_PAUSE: Duration = Duration::from_millis(10);
+/// The packet size assumed by the constant rate profile if the host does not
+/// provide it, i.e., the smallest maximum datagram size of QUIC.
+const DEFAULT_PACKET_SIZE: u64 = 1200;
+
+pub enum Profile {
+    /// Pauses drawn uniformly in `[0, max)`.
+    Uniform { max: Duration },
+    /// Full-size packets sent at `rate` bytes per second.
+    ConstantRate { rate: u64 },
+    /// Pauses drawn from the samples of an empirical distribution.
+    Distribution { gaps: Vec<Duration> },
+    /// Pauses of a recorded trace, replayed in order and looped.
+    Mimic { gaps: Vec<Duration>, next: usize },
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::Uniform { max: DEFAULT_MAX_PAUSE }
+    }
+}
+
+impl Profile {
+    /// The pause before sending the next packet, of `packet_size` bytes.
+    pub fn next_pause(&mut self, rng: &mut fastrand::Rng, packet_size: u64) -> Duration {
+        match self {
+            Profile::Uniform { max } => Duration::from_micros(rng.u64(..max.as_micros().max(1) as u64)),
+            Profile::ConstantRate { rate } => Duration::from_micros(packet_size.saturating_mul(1_000_000) / *rate),
+            Profile::Distribution { gaps } => gaps[rng.usize(..gaps.len())],
+            Profile::Mimic { gaps, next } => {
+                let gap = gaps[*next];
+                *next = (*next + 1) % gaps.len();
+                gap
+            },
+        }
+    }
+}
+
+/// Parse the gaps of a samples file, failing if it has none.
+pub fn parse_gaps(text: &[u8]) -> Option<Vec<Duration>> {
+    let gaps = std::str::from_utf8(text).ok()?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.parse().ok().map(Duration::from_micros))
+        .collect::<Option<Vec<_>>>()?;
+    (!gaps.is_empty()).then_some(gaps)
+}
+
+/// The size of the packets sent by the host.
+pub fn packet_size(penv: &PluginEnv) -> u64 {
+    penv.get_connection(ConnectionField::MaxSendUdpPayloadLength).unwrap_or(DEFAULT_PACKET_SIZE)
+}
+
+/// Select the profile (input 0): 0 for uniform pauses up to input 1
+/// microseconds, 1 for a constant rate of input 1 bytes per second, 2 for the
+/// distribution and 3 for the trace of the loaded samples, which are consumed
+/// even if malformed.
+#[no_mangle]
+pub extern "C" fn plugin_control_80010(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let pd = PLUGIN_DATA.get_mut();
+        let profile = match input::<u64>(penv, 0)? {
+            0 => match input::<u64>(penv, 1)? {
+                0 => return Err(Error::BadInput(1)),
+                max => Profile::Uniform { max: Duration::from_micros(max) },
+            },
+            1 => match input::<u64>(penv, 1)? {
+                0 => return Err(Error::BadInput(1)),
+                rate => Profile::ConstantRate { rate },
+            },
+            kind @ (2 | 3) => {
+                let gaps = parse_gaps(&std::mem::take(&mut pd.samples)).ok_or(Error::InvalidState)?;
+                match kind {
+                    2 => Profile::Distribution { gaps },
+                    _ => Profile::Mimic { gaps, next: 0 },
+                }
+            },
+            _ => return Err(Error::BadInput(0)),
+        };
+        pd.profile = profile;
+        Ok(())
+    })();
+    res.into_code()
+}
+
+/// Append a chunk of the samples file, of input 0 bytes held by the next
+/// inputs as big-endian words.
+#[no_mangle]
+pub extern "C" fn plugin_control_80011(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let len: u64 = input(penv, 0)?;
+        let inputs = penv.get_inputs()?;
+        let mut bytes = poctl::words_to_bytes(&inputs, 1).ok_or(Error::BadInput(1))?;
+        if bytes.len() < len as usize {
+            return Err(Error::BadInput(0));
+        }
+        bytes.truncate(len as usize);
+        PLUGIN_DATA.get_mut().samples.extend_from_slice(&bytes);
+        Ok(())
+    })();
+    res.into_code()
+}
@@ -0,0 +1,73 @@
+//! The size to which the 1-RTT packets are padded. Filling every packet hides
+//! their length entirely but makes each of them max-size. Padding to the next
+//! size of a set of buckets, or to a random size, costs less while still
+//! hiding the exact length.
+
+use pluginop_wasm::{PluginEnv, PluginVal};
+use plugin_support::{input, Error, IntoCode, Result};
+
+use crate::PLUGIN_DATA;
+
+#[derive(Default)]
+pub enum Sizing {
+    /// Fill the packets.
+    #[default]
+    Full,
+    /// Pad to the smallest of the sorted sizes holding the packet.
+    Buckets(Vec<u64>),
+    /// Pad to one of the sizes holding the packet, drawn uniformly.
+    Random(Vec<u64>),
+}
+
+impl Sizing {
+    /// The size to which a packet holding `used` bytes is padded, `None`
+    /// filling it.
+    pub fn pad_to(&self, rng: &mut fastrand::Rng, used: u64) -> Option<u64> {
+        match self {
+            Sizing::Full => None,
+            Sizing::Buckets(sizes) => sizes.iter().copied().find(|s| *s >= used),
+            Sizing::Random(sizes) => {
+                let fitting: Vec<u64> = sizes.iter().copied().filter(|s| *s >= used).collect();
+                (!fitting.is_empty()).then(|| fitting[rng.usize(..fitting.len())])
+            },
+        }
+    }
+}
+
+/// The length of the PADDING frame completing a packet having `left` bytes of
+/// room out of `max`, given the size to pad to.
+pub fn padding_len(pad_to: Option<u64>, left: u64, max: u64) -> u64 {
+    match pad_to {
+        Some(size) => size.saturating_sub(max.saturating_sub(left)).min(left),
+        None => left,
+    }
+}
+
+/// Select the size of the padded packets. Input 0 is the kind of sizing: 0
+/// fills the packets, 1 pads them to the next of the following sizes (e.g.,
+/// 256, 512, 1024) and 2 to a random one of them. The packets larger than all
+/// the sizes are filled.
+#[no_mangle]
+pub extern "C" fn plugin_control_80015(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let kind: u64 = input(penv, 0)?;
+        let mut sizes = Vec::new();
+        for (i, v) in (0..).zip(penv.get_inputs()?).skip(1) {
+            match v {
+                PluginVal::U64(s) if s > 0 => sizes.push(s),
+                _ => return Err(Error::BadInput(i)),
+            }
+        }
+        sizes.sort_unstable();
+        let sizing = match kind {
+            0 => Sizing::Full,
+            1 | 2 if sizes.is_empty() => return Err(Error::BadInput(1)),
+            1 => Sizing::Buckets(sizes),
+            2 => Sizing::Random(sizes),
+            _ => return Err(Error::BadInput(0)),
+        };
+        PLUGIN_DATA.get_mut().sizing = sizing;
+        Ok(())
+    })();
+    res.into_code()
+}
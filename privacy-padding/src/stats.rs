@@ -0,0 +1,124 @@
+//! Statistics on the overhead of the padding and on the delay introduced by
+//! the pauses, such that the privacy they buy can be weighed against their
+//! cost. The application queries them with a plugin control, and can have them
+//! written to a CSV file when the connection closes.
+
+use std::io::Write;
+
+use pluginop_wasm::{fd::FileDescriptor, Duration, PluginEnv, UnixInstant};
+use plugin_support::{input, poctl, save_all, Error, IntoCode, Result};
+
+use crate::PLUGIN_DATA;
+
+#[derive(Default)]
+pub struct Stats {
+    /// The PADDING bytes written, including the ones of the chaff packets.
+    padded_bytes: u64,
+    chaff_packets: u64,
+    /// The number of times the send gate held 1-RTT packets.
+    pauses: u64,
+    /// The total time during which the send gate held 1-RTT packets.
+    delay: Duration,
+    /// Since when the send gate holds 1-RTT packets.
+    held_since: Option<UnixInstant>,
+    /// The file to write at close, if any.
+    file: Option<String>,
+    /// Whether the file was written.
+    dumped: bool,
+}
+
+impl Stats {
+    pub fn on_padding(&mut self, len: u64) {
+        self.padded_bytes += len;
+    }
+
+    pub fn on_chaff(&mut self) {
+        self.chaff_packets += 1;
+    }
+
+    /// Account for the send gate holding (`held`) or letting go the 1-RTT
+    /// packets at `now`. A pause only counts if a packet was actually held.
+    pub fn on_gate(&mut self, now: UnixInstant, held: bool) {
+        match (held, self.held_since) {
+            (true, None) => self.held_since = Some(now),
+            (false, Some(since)) => {
+                self.pauses += 1;
+                self.delay += now.saturating_duration_since(since);
+                self.held_since = None;
+            },
+            _ => {},
+        }
+    }
+
+    fn csv(&self) -> String {
+        format!(
+            "padded_bytes,chaff_packets,pauses,delay_us\n{},{},{},{}\n",
+            self.padded_bytes, self.chaff_packets, self.pauses, self.delay.as_micros(),
+        )
+    }
+}
+
+/// Write the statistics to the configured file, once.
+fn dump(penv: &PluginEnv) {
+    let stats = &mut PLUGIN_DATA.get_mut().stats;
+    let Some(name) = &stats.file else { return };
+    if stats.dumped {
+        return;
+    }
+    stats.dumped = true;
+    let res = FileDescriptor::create(name).and_then(|mut fd| fd.write_all(stats.csv().as_bytes()));
+    if res.is_err() {
+        penv.print("cannot write the privacy-padding statistics");
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn post_write_frame_1c(penv: &mut PluginEnv) -> i64 {
+    dump(penv);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn post_write_frame_1d(penv: &mut PluginEnv) -> i64 {
+    dump(penv);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn post_process_frame_1c(penv: &mut PluginEnv) -> i64 {
+    dump(penv);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn post_process_frame_1d(penv: &mut PluginEnv) -> i64 {
+    dump(penv);
+    0
+}
+
+/// Return the padded bytes, the chaff packets, the pauses and their total
+/// delay so far.
+#[no_mangle]
+pub extern "C" fn plugin_control_80013(penv: &mut PluginEnv) -> i64 {
+    let stats = &PLUGIN_DATA.stats;
+    let vals = [stats.padded_bytes.into(), stats.chaff_packets.into(), stats.pauses.into(), stats.delay.into()];
+    save_all(penv, &vals).into_code()
+}
+
+/// Write the statistics to a file when the connection closes. Takes the length
+/// of its name in bytes, followed by the UTF-8 name as 64-bit words.
+#[no_mangle]
+pub extern "C" fn plugin_control_80014(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let len: u64 = input(penv, 0)?;
+        let inputs = penv.get_inputs()?;
+        let mut bytes = poctl::words_to_bytes(&inputs, 1).ok_or(Error::BadInput(1))?;
+        if bytes.len() < len as usize {
+            return Err(Error::BadInput(0));
+        }
+        bytes.truncate(len as usize);
+        PLUGIN_DATA.get_mut().stats.file = Some(String::from_utf8(bytes).map_err(|_| Error::BadInput(1))?);
+        Ok(())
+    })();
+    res.into_code()
+}
@@ -0,0 +1,57 @@
+//! The pauses for the hosts not calling the `send_gate` protocol operation,
+//! such as the one of `pluginop` 0.1. During a pause, a fake frame is sent
+//! first in the 1-RTT packets, and failing its preparation with
+//! [`Error::StopSending`] makes the host stop building the packet. A timer
+//! wakes the host up once the pause elapsed.
+
+use pluginop_wasm::{PluginEnv, UnixInstant, quic::{FrameRegistration, FrameSendKind, FrameSendOrder, Registration}};
+use plugin_support::{frame, input, save, send_gate::Decision, Error, IntoCode, Result};
+
+/// The frame whose preparation stops the building of the current packet.
+pub const STOP_FRAME_TYPE: u64 = 0xaaaa;
+/// The timer resuming the sending.
+pub const RESUME_TIMER: u64 = 7;
+
+pub fn register(penv: &mut PluginEnv) -> Result<()> {
+    penv.register(Registration::Frame(FrameRegistration::new(STOP_FRAME_TYPE, FrameSendOrder::First, FrameSendKind::OncePerPacket, false, true)))
+        .map_err(|_| Error::Registration)
+}
+
+/// Wake the host up at `at`, the end of the pause.
+pub fn arm(penv: &mut PluginEnv, at: UnixInstant) -> Result<()> {
+    penv.set_timer(at, 1, RESUME_TIMER)?;
+    Ok(())
+}
+
+#[no_mangle]
+pub extern "C" fn should_send_frame_aaaa(penv: &mut PluginEnv) -> i64 {
+    let res: Result<()> = (|| {
+        let ss = frame::ShouldSend::get(penv)?;
+        let now = input::<UnixInstant>(penv, 4)?;
+        save(penv, crate::gate(ss.packet_type, now) != Decision::Send)
+    })();
+    res.into_code()
+}
+
+#[no_mangle]
+pub extern "C" fn prepare_frame_aaaa(_penv: &mut PluginEnv) -> i64 {
+    // Specific error code to stop the sending processing.
+    Error::StopSending.code()
+}
+
+// The frame is never prepared, so it is neither measured nor written.
+#[no_mangle]
+pub extern "C" fn wire_len_aaaa(_penv: &mut PluginEnv) -> i64 {
+    Error::InvalidState.code()
+}
+
+#[no_mangle]
+pub extern "C" fn write_frame_aaaa(_penv: &mut PluginEnv) -> i64 {
+    Error::InvalidState.code()
+}
+
+#[no_mangle]
+pub extern "C" fn on_plugin_timeout_7(_penv: &mut PluginEnv) -> i64 {
+    // The host tries to send again after a timer, nothing else to do.
+    0
+}
@@ -0,0 +1,185 @@
+//! Checks of the protocol operations exported by a plugin against what it
+//! registers in `init`.
+
+use std::fmt;
+
+use pluginop_common::{quic::Registration, Anchor, PluginOp};
+
+/// The largest frame type defined by RFC 9000. The host handles these frames
+/// by itself, so plugins can replace some of their operations without
+/// registering them.
+const MAX_RFC9000_FRAME_TYPE: u64 = 0x1e;
+
+/// The names of the protocol operations having a hexadecimal parameter.
+const PARAMETRIZED: [&str; 13] = [
+    "decode_transport_parameter_",
+    "write_transport_parameter_",
+    "log_frame_",
+    "notify_frame_",
+    "on_frame_reserved_",
+    "parse_frame_",
+    "prepare_frame_",
+    "process_frame_",
+    "should_send_frame_",
+    "wire_len_",
+    "write_frame_",
+    "plugin_control_",
+    "on_plugin_timeout_",
+];
+
+/// The host stores the names of the other protocol operations in 32 bytes.
+const MAX_OTHER_NAME_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Debug)]
+pub struct Problem {
+    pub level: Level,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level {
+            Level::Error => write!(f, "error: {}", self.message),
+            Level::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+pub fn error(message: String) -> Problem {
+    Problem { level: Level::Error, message }
+}
+
+pub fn warning(message: String) -> Problem {
+    Problem { level: Level::Warning, message }
+}
+
+/// The frame type on which `po` operates, if any.
+pub fn frame_type(po: &PluginOp) -> Option<u64> {
+    match *po {
+        PluginOp::LogFrame(ty)
+        | PluginOp::NotifyFrame(ty)
+        | PluginOp::OnFrameReserved(ty)
+        | PluginOp::ParseFrame(ty)
+        | PluginOp::PrepareFrame(ty)
+        | PluginOp::ProcessFrame(ty)
+        | PluginOp::ShouldSendFrame(ty)
+        | PluginOp::WireLen(ty)
+        | PluginOp::WriteFrame(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+/// Parse an exported function name as the host does, without panicking on
+/// names that the host would reject.
+pub fn parse(name: &str) -> Result<(PluginOp, Anchor), String> {
+    let po_name = ["pre_", "before_", "post_", "after_"]
+        .iter()
+        .find_map(|a| name.strip_prefix(a))
+        .unwrap_or(name);
+    if let Some(prefix) = PARAMETRIZED.iter().find(|p| po_name.starts_with(*p)) {
+        let param = &po_name[prefix.len()..];
+        if u64::from_str_radix(param, 16).is_err() {
+            return Err(format!("`{name}` does not end with a hexadecimal parameter, the host would panic when loading it"));
+        }
+    }
+    let (po, anchor) = PluginOp::from_name(name);
+    if matches!(po, PluginOp::Other(_)) && po_name.len() > MAX_OTHER_NAME_LEN {
+        return Err(format!("`{name}` is longer than {MAX_OTHER_NAME_LEN} bytes, the host would panic when loading it"));
+    }
+    Ok((po, anchor))
+}
+
+/// Check the exported `functions` against the `registrations` of the plugin.
+pub fn check(functions: &[String], registrations: &[Registration]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut defined = Vec::new();
+    for f in functions {
+        match parse(f) {
+            Ok((po, Anchor::Define)) => defined.push((f, po)),
+            Ok(_) => {},
+            Err(e) => problems.push(error(e)),
+        }
+    }
+    let has = |po: PluginOp| defined.iter().any(|(_, d)| *d == po);
+
+    if !has(PluginOp::Init) {
+        problems.push(warning("no `init` protocol operation".to_string()));
+    }
+
+    let mut frames = Vec::new();
+    let mut tps = Vec::new();
+    for r in registrations {
+        match r {
+            Registration::Frame(fr) => frames.push(fr.get_type()),
+            Registration::TransportParameter(ty) => tps.push(*ty),
+        }
+    }
+
+    for &ty in &frames {
+        for (po, name) in [(PluginOp::ShouldSendFrame(ty), "should_send_frame"), (PluginOp::PrepareFrame(ty), "prepare_frame")] {
+            if !has(po) {
+                problems.push(error(format!("frame 0x{ty:x} is registered but `{name}_{ty:x}` is missing")));
+            }
+        }
+        if ty <= MAX_RFC9000_FRAME_TYPE {
+            // The host knows how to handle the rest.
+            continue;
+        }
+        for (po, name) in [(PluginOp::WireLen(ty), "wire_len"), (PluginOp::WriteFrame(ty), "write_frame")] {
+            if !has(po) {
+                problems.push(error(format!("frame 0x{ty:x} is registered but `{name}_{ty:x}` is missing")));
+            }
+        }
+        for (po, name) in [(PluginOp::ParseFrame(ty), "parse_frame"), (PluginOp::ProcessFrame(ty), "process_frame")] {
+            if !has(po) {
+                problems.push(warning(format!("frame 0x{ty:x} cannot be received, `{name}_{ty:x}` is missing")));
+            }
+        }
+        let optional = [
+            (PluginOp::LogFrame(ty), "log_frame"),
+            (PluginOp::NotifyFrame(ty), "notify_frame"),
+            (PluginOp::OnFrameReserved(ty), "on_frame_reserved"),
+        ];
+        for (po, name) in optional {
+            if !has(po) {
+                problems.push(warning(format!("frame 0x{ty:x} is registered but `{name}_{ty:x}` is missing")));
+            }
+        }
+    }
+
+    for &ty in &tps {
+        let ops = [
+            (PluginOp::DecodeTransportParameter(ty), "decode_transport_parameter"),
+            (PluginOp::WriteTransportParameter(ty), "write_transport_parameter"),
+        ];
+        for (po, name) in ops {
+            if !has(po) {
+                problems.push(error(format!("transport parameter 0x{ty:x} is registered but `{name}_{ty:x}` is missing")));
+            }
+        }
+    }
+
+    // Catch the operations whose parameter does not match any registration.
+    for (name, po) in &defined {
+        if let Some(ty) = frame_type(po) {
+            if ty > MAX_RFC9000_FRAME_TYPE && !frames.contains(&ty) {
+                problems.push(error(format!("`{name}` is exported but frame 0x{ty:x} is not registered in `init`")));
+            }
+            continue;
+        }
+        match *po {
+            PluginOp::DecodeTransportParameter(ty) | PluginOp::WriteTransportParameter(ty) if !tps.contains(&ty) => {
+                problems.push(error(format!("`{name}` is exported but transport parameter 0x{ty:x} is not registered in `init`")));
+            },
+            _ => {},
+        }
+    }
+
+    problems
+}
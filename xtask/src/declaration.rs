@@ -0,0 +1,158 @@
+//! The manifest embedded in a plugin by `plugin_manifest!`, declaring what it
+//! registers, and the checks of these declarations against what the plugin
+//! does and against the other plugins loaded with it.
+
+use std::collections::BTreeMap;
+
+use plugin_support::manifest::KEYS;
+use pluginop_common::{quic::Registration, Anchor, PluginOp};
+
+use crate::check::{self, error, Problem};
+
+#[derive(Debug, Default)]
+pub struct Declaration {
+    pub name: String,
+    pub frames: Vec<u64>,
+    pub transport_parameters: Vec<u64>,
+    pub timers: Vec<u64>,
+    pub plugin_controls: Vec<u64>,
+    pub calls: Vec<u64>,
+}
+
+/// The timers and plugin controls defined by the exported `functions`.
+fn exported(functions: &[String]) -> (Vec<u64>, Vec<u64>) {
+    let mut timers = Vec::new();
+    let mut plugin_controls = Vec::new();
+    for f in functions {
+        match check::parse(f) {
+            Ok((PluginOp::OnPluginTimeout(id), Anchor::Define)) => timers.push(id),
+            Ok((PluginOp::PluginControl(id), Anchor::Define)) => plugin_controls.push(id),
+            _ => {},
+        }
+    }
+    (timers, plugin_controls)
+}
+
+fn registered(registrations: &[Registration]) -> (Vec<u64>, Vec<u64>) {
+    let mut frames = Vec::new();
+    let mut tps = Vec::new();
+    for r in registrations {
+        match r {
+            Registration::Frame(fr) => frames.push(fr.get_type()),
+            Registration::TransportParameter(ty) => tps.push(*ty),
+        }
+    }
+    (frames, tps)
+}
+
+/// Report the values of `declared` and `actual` that are not in the other.
+fn compare(problems: &mut Vec<Problem>, what: &str, declared: &[u64], actual: &[u64], action: &str) {
+    for v in declared.iter().filter(|v| !actual.contains(v)) {
+        problems.push(error(format!("the manifest declares {what} 0x{v:x} but the plugin does not {action} it")));
+    }
+    for v in actual.iter().filter(|v| !declared.contains(v)) {
+        problems.push(error(format!("the plugin {action}s {what} 0x{v:x} but the manifest does not declare it")));
+    }
+}
+
+impl Declaration {
+    /// Parse the content of the manifest section.
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let text = std::str::from_utf8(data).map_err(|_| "the manifest is not UTF-8".to_string())?;
+        let mut decl = Declaration::default();
+        for line in text.lines() {
+            let (key, value) = line.split_once(' ').ok_or_else(|| format!("invalid manifest line `{line}`"))?;
+            if key == "name" {
+                decl.name = value.to_string();
+                continue;
+            }
+            let v = value.strip_prefix("0x")
+                .and_then(|v| u64::from_str_radix(v, 16).ok())
+                .ok_or_else(|| format!("invalid value in manifest line `{line}`"))?;
+            let list = match KEYS.iter().position(|k| *k == key) {
+                Some(0) => &mut decl.frames,
+                Some(1) => &mut decl.transport_parameters,
+                Some(2) => &mut decl.timers,
+                Some(3) => &mut decl.plugin_controls,
+                Some(4) => &mut decl.calls,
+                _ => return Err(format!("unknown key in manifest line `{line}`")),
+            };
+            list.push(v);
+        }
+        Ok(decl)
+    }
+
+    /// The declaration of a plugin without manifest, from what it does.
+    pub fn infer(name: &str, functions: &[String], registrations: &[Registration]) -> Self {
+        let (frames, transport_parameters) = registered(registrations);
+        let (timers, plugin_controls) = exported(functions);
+        Declaration { name: name.to_string(), frames, transport_parameters, timers, plugin_controls, calls: Vec::new() }
+    }
+
+    /// Check the declaration of the `name` plugin against its exported
+    /// `functions` and its `registrations`.
+    pub fn check(&self, name: &str, functions: &[String], registrations: &[Registration]) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        if self.name != name {
+            problems.push(error(format!("the manifest is named `{}` instead of `{name}`", self.name)));
+        }
+        let (frames, tps) = registered(registrations);
+        let (timers, plugin_controls) = exported(functions);
+        compare(&mut problems, "frame", &self.frames, &frames, "register");
+        compare(&mut problems, "transport parameter", &self.transport_parameters, &tps, "register");
+        compare(&mut problems, "timer", &self.timers, &timers, "handle");
+        compare(&mut problems, "plugin control", &self.plugin_controls, &plugin_controls, "provide");
+        problems
+    }
+}
+
+/// Check that the `plugins`, given with their exported functions, can be
+/// loaded together.
+pub fn check_set(plugins: &[(&Declaration, &[String])]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut owners: BTreeMap<(&str, u64), Vec<&str>> = BTreeMap::new();
+    let mut definitions: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (decl, functions) in plugins {
+        let lists = [
+            ("frame", &decl.frames),
+            ("transport parameter", &decl.transport_parameters),
+            ("timer", &decl.timers),
+            ("plugin control", &decl.plugin_controls),
+        ];
+        for (what, list) in lists {
+            for &v in list {
+                owners.entry((what, v)).or_default().push(&decl.name);
+            }
+        }
+        for f in functions.iter() {
+            let Ok((po, Anchor::Define)) = check::parse(f) else { continue };
+            // The other operations are covered by the declarations.
+            let covered = match po {
+                PluginOp::Init | PluginOp::OnPluginTimeout(_) | PluginOp::PluginControl(_) => true,
+                PluginOp::DecodeTransportParameter(ty) | PluginOp::WriteTransportParameter(ty) => decl.transport_parameters.contains(&ty),
+                _ => check::frame_type(&po).is_some_and(|ty| decl.frames.contains(&ty)),
+            };
+            if !covered {
+                definitions.entry(f.as_str()).or_default().push(&decl.name);
+            }
+        }
+    }
+    for ((what, v), names) in &owners {
+        if names.len() > 1 {
+            problems.push(error(format!("{what} 0x{v:x} is used by {}", names.join(", "))));
+        }
+    }
+    for (f, names) in &definitions {
+        if names.len() > 1 {
+            problems.push(error(format!("`{f}` is defined by {}", names.join(", "))));
+        }
+    }
+    for (decl, _) in plugins {
+        for v in &decl.calls {
+            if !owners.contains_key(&("plugin control", *v)) {
+                problems.push(error(format!("{} calls plugin control 0x{v:x} but no plugin provides it", decl.name)));
+            }
+        }
+    }
+    problems
+}
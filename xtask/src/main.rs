@@ -0,0 +1,238 @@
+//! Development tasks of this repository, run with `cargo xtask <task>`.
+//!
+//! - `build [--out-dir <dir>] [plugin...]`: compile the plugins (all of them
+//!   by default) for `wasm32-unknown-unknown`, strip their custom sections,
+//!   check that their exported protocol operations match what they register
+//!   in `init`, and write them with a `manifest.json` to `target/plugins` (or
+//!   the provided directory). Registrations colliding between the plugins
+//!   are reported as warnings.
+//! - `check [plugin...]`: compile and check the plugins (all of them by
+//!   default) as a set loaded together, reporting the colliding frame types,
+//!   transport parameters, timers, plugin controls and protocol operations,
+//!   as well as the plugin controls called but not provided, as errors.
+//!
+//! Each plugin embeds its declarations with `plugin_support::plugin_manifest!`,
+//! which are checked against what it actually registers and exports.
+
+use std::{path::{Path, PathBuf}, process::{Command, ExitCode}};
+
+use check::{Level, Problem};
+use declaration::Declaration;
+use plugin_harness::{quic::ConnectionField, Plugin};
+use serde::Deserialize;
+
+mod check;
+mod declaration;
+mod manifest;
+mod strip;
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_root: PathBuf,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    crate_types: Vec<String>,
+}
+
+fn cargo() -> Command {
+    Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+}
+
+fn metadata() -> Result<Metadata, String> {
+    let output = cargo()
+        .args(["metadata", "--no-deps", "--format-version", "1"])
+        .output()
+        .map_err(|e| format!("cannot run cargo metadata: {e}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("invalid cargo metadata: {e}"))
+}
+
+/// The workspace members compiled as WebAssembly modules.
+fn plugins(metadata: &Metadata) -> Vec<String> {
+    let mut plugins: Vec<String> = metadata.packages.iter()
+        .filter(|p| p.targets.iter().any(|t| t.crate_types.iter().any(|c| c == "cdylib")))
+        .map(|p| p.name.clone())
+        .collect();
+    plugins.sort();
+    plugins
+}
+
+/// A compiled plugin, checked on its own.
+struct Processed {
+    name: String,
+    file: String,
+    module: Vec<u8>,
+    functions: Vec<String>,
+    declaration: Declaration,
+    entry: manifest::Plugin,
+    problems: Vec<Problem>,
+}
+
+/// Strip, load and check a compiled plugin.
+fn process(name: &str, wasm: &Path) -> Result<Processed, String> {
+    let module = std::fs::read(wasm).map_err(|e| format!("cannot read {}: {e}", wasm.display()))?;
+    let stripped = strip::strip(&module).ok_or_else(|| format!("{} is not a valid WebAssembly module", wasm.display()))?;
+    let file = wasm.file_name().expect("wasm file").to_string_lossy().into_owned();
+
+    let mut plugin = Plugin::new(&stripped).map_err(|e| format!("cannot instantiate {file}: {e}"))?;
+    let functions = plugin.functions();
+    let mut problems = Vec::new();
+    // Plugins may look at the connection when initializing.
+    plugin.host_mut().set_connection(ConnectionField::IsServer, false);
+    plugin.host_mut().set_connection(ConnectionField::IsEstablished, false);
+    if plugin.exports("init") {
+        match plugin.try_call("init", &[]) {
+            Ok(0) => {},
+            Ok(code) => problems.push(check::error(format!("`init` returned {code}"))),
+            Err(e) => problems.push(check::error(format!("`init` trapped: {e}"))),
+        }
+    }
+    let registrations = plugin.host().registrations.clone();
+    problems.extend(check::check(&functions, &registrations));
+    let declaration = match strip::manifest(&stripped).map(Declaration::parse) {
+        Some(Ok(declaration)) => {
+            problems.extend(declaration.check(name, &functions, &registrations));
+            declaration
+        },
+        Some(Err(e)) => {
+            problems.push(check::error(e));
+            Declaration::infer(name, &functions, &registrations)
+        },
+        None => {
+            problems.push(check::warning("no manifest, use `plugin_support::plugin_manifest!` to declare what the plugin uses".to_string()));
+            Declaration::infer(name, &functions, &registrations)
+        },
+    };
+    let entry = manifest::Plugin::new(name, &file, stripped.len(), &functions, &registrations, &declaration);
+    Ok(Processed { name: name.to_string(), file, module: stripped, functions, declaration, entry, problems })
+}
+
+fn print(problems: &[Problem]) -> bool {
+    for p in problems {
+        println!("  {p}");
+    }
+    problems.iter().all(|p| p.level != Level::Error)
+}
+
+/// Compile the `selected` plugins and process them.
+fn compile(metadata: &Metadata, selected: &[String]) -> Result<Vec<Processed>, String> {
+    let mut cmd = cargo();
+    cmd.current_dir(&metadata.workspace_root).args(["build", "--release", "--target", TARGET]);
+    for p in selected {
+        cmd.args(["-p", p]);
+    }
+    let status = cmd.status().map_err(|e| format!("cannot run cargo build: {e}"))?;
+    if !status.success() {
+        return Err(format!("failed to compile the plugins, is the {TARGET} target installed?"));
+    }
+
+    let wasm_dir = metadata.target_directory.join(TARGET).join("release");
+    selected.iter()
+        .map(|name| process(name, &wasm_dir.join(format!("{}.wasm", name.replace('-', "_")))))
+        .collect()
+}
+
+/// Check the `plugins` as a set loaded together, with `level` as the level of
+/// the problems found.
+fn check_set(plugins: &[Processed], level: Level) -> bool {
+    let set: Vec<_> = plugins.iter().map(|p| (&p.declaration, p.functions.as_slice())).collect();
+    let mut problems = declaration::check_set(&set);
+    for p in &mut problems {
+        p.level = level;
+    }
+    if !problems.is_empty() {
+        println!("together:");
+    }
+    print(&problems)
+}
+
+/// Parse the plugins among `args`, calling `option` on the other arguments
+/// starting with `--`.
+fn select(all: Vec<String>, args: &[String], mut option: impl FnMut(&str, &mut std::slice::Iter<String>) -> Result<(), String>) -> Result<Vec<String>, String> {
+    let mut selected = Vec::new();
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            o if o.starts_with("--") => option(o, &mut args)?,
+            p if all.iter().any(|n| n == p) => selected.push(p.to_string()),
+            p => return Err(format!("unknown plugin `{p}`, expected one of {}", all.join(", "))),
+        }
+    }
+    Ok(if selected.is_empty() { all } else { selected })
+}
+
+fn build(args: &[String]) -> Result<bool, String> {
+    let metadata = metadata()?;
+    let mut out_dir = metadata.target_directory.join("plugins");
+    let selected = select(plugins(&metadata), args, |o, args| match o {
+        "--out-dir" => {
+            out_dir = args.next().ok_or("--out-dir expects a directory")?.into();
+            Ok(())
+        },
+        o => Err(format!("unknown option `{o}`")),
+    })?;
+    let plugins = compile(&metadata, &selected)?;
+
+    std::fs::create_dir_all(&out_dir).map_err(|e| format!("cannot create {}: {e}", out_dir.display()))?;
+    let mut manifest = manifest::Manifest::default();
+    let mut ok = true;
+    for p in &plugins {
+        std::fs::write(out_dir.join(&p.file), &p.module).map_err(|e| format!("cannot write {}: {e}", p.file))?;
+        println!("{}: {} protocol operations, {} bytes", p.name, p.entry.protocol_operations.len(), p.entry.size);
+        ok &= print(&p.problems);
+    }
+    check_set(&plugins, Level::Warning);
+    manifest.plugins = plugins.into_iter().map(|p| p.entry).collect();
+    let manifest_path = out_dir.join("manifest.json");
+    let json = serde_json::to_string_pretty(&manifest).expect("serializable manifest");
+    std::fs::write(&manifest_path, json + "\n").map_err(|e| format!("cannot write {}: {e}", manifest_path.display()))?;
+    println!("Wrote {} plugins and their manifest to {}", selected.len(), out_dir.display());
+    Ok(ok)
+}
+
+fn check(args: &[String]) -> Result<bool, String> {
+    let metadata = metadata()?;
+    let selected = select(plugins(&metadata), args, |o, _| Err(format!("unknown option `{o}`")))?;
+    let plugins = compile(&metadata, &selected)?;
+    let mut ok = true;
+    for p in &plugins {
+        println!("{}:", p.name);
+        ok &= print(&p.problems);
+    }
+    ok &= check_set(&plugins, Level::Error);
+    if ok {
+        println!("{} can be loaded together", selected.join(", "));
+    }
+    Ok(ok)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let res = match args.first().map(String::as_str) {
+        Some("build") => build(&args[1..]),
+        Some("check") => check(&args[1..]),
+        _ => Err("usage: cargo xtask build [--out-dir <dir>] [plugin...]\n       cargo xtask check [plugin...]".to_string()),
+    };
+    match res {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -0,0 +1,79 @@
+//! The manifest describing the built plugins.
+
+use pluginop_common::{quic::Registration, Anchor, PluginOp};
+use serde::Serialize;
+
+use crate::{check, declaration::Declaration};
+
+#[derive(Debug, Serialize)]
+pub struct Frame {
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub send_order: String,
+    pub ack_eliciting: bool,
+    pub count_for_in_flight: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Plugin {
+    pub name: String,
+    pub file: String,
+    pub size: usize,
+    pub protocol_operations: Vec<String>,
+    pub frames: Vec<Frame>,
+    pub transport_parameters: Vec<String>,
+    pub plugin_controls: Vec<String>,
+    pub timers: Vec<String>,
+    pub calls: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Manifest {
+    pub plugins: Vec<Plugin>,
+}
+
+fn hex(v: u64) -> String {
+    format!("0x{v:x}")
+}
+
+impl Plugin {
+    pub fn new(name: &str, file: &str, size: usize, functions: &[String], registrations: &[Registration], declaration: &Declaration) -> Self {
+        let mut protocol_operations = functions.to_vec();
+        protocol_operations.sort();
+        let mut frames = Vec::new();
+        let mut transport_parameters = Vec::new();
+        for r in registrations {
+            match r {
+                Registration::Frame(fr) => frames.push(Frame {
+                    ty: hex(fr.get_type()),
+                    send_order: format!("{:?}", fr.send_order()),
+                    ack_eliciting: fr.ack_eliciting(),
+                    count_for_in_flight: fr.count_for_in_flight(),
+                }),
+                Registration::TransportParameter(ty) => transport_parameters.push(hex(*ty)),
+            }
+        }
+        let mut plugin_controls = Vec::new();
+        let mut timers = Vec::new();
+        for f in functions {
+            match check::parse(f) {
+                Ok((PluginOp::PluginControl(id), Anchor::Define)) => plugin_controls.push(id),
+                Ok((PluginOp::OnPluginTimeout(id), Anchor::Define)) => timers.push(id),
+                _ => {},
+            }
+        }
+        plugin_controls.sort();
+        timers.sort();
+        Plugin {
+            name: name.to_string(),
+            file: file.to_string(),
+            size,
+            protocol_operations,
+            frames,
+            transport_parameters,
+            plugin_controls: plugin_controls.into_iter().map(hex).collect(),
+            timers: timers.into_iter().map(hex).collect(),
+            calls: declaration.calls.iter().copied().map(hex).collect(),
+        }
+    }
+}
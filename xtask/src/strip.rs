@@ -0,0 +1,75 @@
+//! Removal of the custom sections (debug information, names, producers) of a
+//! WebAssembly module, which are useless to the host, except for the manifest
+//! of the plugin.
+
+use plugin_support::manifest::SECTION as MANIFEST_SECTION;
+
+const HEADER_LEN: usize = 8;
+const CUSTOM_SECTION_ID: u8 = 0;
+
+/// Read the LEB128-encoded `u32` at the start of `b`, returning it with its
+/// length.
+fn read_u32(b: &[u8]) -> Option<(u32, usize)> {
+    let mut v: u32 = 0;
+    for (i, byte) in b.iter().take(5).enumerate() {
+        v |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((v, i + 1));
+        }
+    }
+    None
+}
+
+struct Section<'a> {
+    id: u8,
+    /// The whole encoding of the section.
+    raw: &'a [u8],
+    content: &'a [u8],
+}
+
+/// The sections of `module`. Returns `None` if the module is malformed.
+fn sections(module: &[u8]) -> Option<Vec<Section<'_>>> {
+    if module.len() < HEADER_LEN || &module[..4] != b"\0asm" {
+        return None;
+    }
+    let mut sections = Vec::new();
+    let mut rest = &module[HEADER_LEN..];
+    while let Some((&id, after_id)) = rest.split_first() {
+        let (size, size_len) = read_u32(after_id)?;
+        let section_len = 1 + size_len + size as usize;
+        if rest.len() < section_len {
+            return None;
+        }
+        sections.push(Section { id, raw: &rest[..section_len], content: &rest[1 + size_len..section_len] });
+        rest = &rest[section_len..];
+    }
+    Some(sections)
+}
+
+/// Split the content of a custom section into its name and data.
+fn custom_section(content: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (name_len, len) = read_u32(content)?;
+    let end = len.checked_add(name_len as usize)?;
+    Some((content.get(len..end)?, &content[end..]))
+}
+
+/// Copy `module` without its custom sections, but the manifest one. Returns
+/// `None` if the module is malformed.
+pub fn strip(module: &[u8]) -> Option<Vec<u8>> {
+    let mut out = module.get(..HEADER_LEN)?.to_vec();
+    for s in sections(module)? {
+        if s.id != CUSTOM_SECTION_ID || custom_section(s.content)?.0 == MANIFEST_SECTION.as_bytes() {
+            out.extend_from_slice(s.raw);
+        }
+    }
+    Some(out)
+}
+
+/// The data of the manifest section of `module`, if any.
+pub fn manifest(module: &[u8]) -> Option<&[u8]> {
+    sections(module)?
+        .into_iter()
+        .filter(|s| s.id == CUSTOM_SECTION_ID)
+        .filter_map(|s| custom_section(s.content))
+        .find_map(|(name, data)| (name == MANIFEST_SECTION.as_bytes()).then_some(data))
+}